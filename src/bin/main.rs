@@ -1,6 +1,13 @@
-use std::{io::stdin, time::Duration};
+use std::{
+    io::stdin,
+    sync::{atomic::Ordering, Arc},
+    thread::{self, JoinHandle},
+    time::Duration,
+};
 
-use chessai::chess_engine::{book::Book, gen_moves::Move, transposition_table::TranspositionTable, ChessState};
+use chessai::chess_engine::{
+    book::Book, gen_moves::Move, options::EngineOptions, search::SearchSignals, time_manager::SearchLimits, transposition_table::TranspositionTable, ChessState,
+};
 
 fn perft(state: &mut ChessState, m: Option<&Move>, depth: u32) -> [u64; 6] {
     if depth == 0 {
@@ -35,68 +42,264 @@ fn perft(state: &mut ChessState, m: Option<&Move>, depth: u32) -> [u64; 6] {
         })
 }
 
-fn fmt_moves(moves: &[Move]) -> String {
-    let mut s = String::new();
-    for m in moves {
-        s.push_str(&format!("{} ", m));
+/// Parses the arguments of the go command, unknown arguments are ignored
+fn parse_go(args: &[&str]) -> Result<SearchLimits, String> {
+    fn value<T: std::str::FromStr>(name: &str, value: Option<&&str>) -> Result<T, String> {
+        value.and_then(|v| v.parse().ok()).ok_or(format!("Invalid value for {}!", name))
     }
-    s
+
+    let mut limits = SearchLimits::default();
+    let mut args = args.iter();
+    while let Some(arg) = args.next() {
+        match *arg {
+            "depth" => limits.depth = Some(value(arg, args.next())?),
+            "nodes" => limits.nodes = Some(value(arg, args.next())?),
+            "mate" => limits.mate = Some(value(arg, args.next())?),
+            "movetime" => limits.movetime = Some(Duration::from_millis(value(arg, args.next())?)),
+            "infinite" => limits.infinite = true,
+            "ponder" => limits.ponder = true,
+            "wtime" => limits.wtime = Some(Duration::from_millis(value::<i64>(arg, args.next())?.max(0) as u64)),
+            "btime" => limits.btime = Some(Duration::from_millis(value::<i64>(arg, args.next())?.max(0) as u64)),
+            "winc" => limits.winc = Duration::from_millis(value(arg, args.next())?),
+            "binc" => limits.binc = Duration::from_millis(value(arg, args.next())?),
+            "movestogo" => limits.movestogo = Some(value(arg, args.next())?),
+            _ => (),
+        }
+    }
+
+    Ok(limits)
 }
 
-fn main() {
-    println!("Stupid chess engine by Jan");
+/// Splits a line into the command and its arguments
+///
+/// Unknown tokens in front of a command are skipped as required by the UCI protocol.
+fn split_command(line: &str) -> Option<(&str, Vec<&str>)> {
+    let tokens = line.split_whitespace().collect::<Vec<_>>();
+    let i = tokens.iter().position(|t| COMMANDS.contains(t))?;
+    Some((tokens[i], tokens[i + 1..].to_vec()))
+}
 
-    println!("Loading book...");
-    Book::load("book.bin").expect("Failed to load book");
+/// Parses the arguments of the setoption command into the name and the value of the option, both may contain spaces
+fn parse_setoption(args: &[&str]) -> Result<(String, Option<String>), String> {
+    if args.first() != Some(&"name") {
+        return Err("Expected setoption name <id> [value <x>]".to_string());
+    }
 
-    println!("Allocating memory for transposition table...");
-    TranspositionTable::init();
+    let value_index = args.iter().position(|a| *a == "value");
+    let name = args[1..value_index.unwrap_or(args.len())].join(" ");
+    let value = value_index.map(|i| args[i + 1..].join(" "));
 
-    println!("uciok");
+    Ok((name, value))
+}
 
-    let mut state = ChessState::default();
-    loop {
-        let mut line = String::new();
-        stdin().read_line(&mut line).unwrap();
+/// Sets up the position of the position command including the moves leading up to it
+///
+/// An illegal move and all moves after it are ignored, the returned warning tells which move it was.
+fn parse_position(args: &[&str]) -> Result<(ChessState, Option<String>), String> {
+    // Everything after "moves" is the game history leading up to the current position
+    let (setup, moves) = match args.iter().position(|a| *a == "moves") {
+        Some(i) => (&args[..i], &args[i + 1..]),
+        None => (args, &[][..]),
+    };
 
-        let mut splits = line.trim_end().split(' ');
-        let cmd = match splits.next() {
-            Some(cmd) => cmd,
-            None => continue,
-        };
+    let mut state = match setup.first() {
+        Some(&"startpos") => ChessState::from_fen(STARTPOS_FEN).unwrap(),
+        Some(&"fen") => {
+            if setup.len() < 2 {
+                return Err("Missing fen!".to_string());
+            }
 
-        let args = splits.collect::<Vec<_>>();
-        match cmd {
-            "position" => {
-                if args.is_empty() {
-                    println!("Invalid amount of arguments!");
-                    continue;
-                }
+            ChessState::from_fen(setup[1..].join(" ").as_str())?
+        }
+        Some(_) => return Err("Invalid argument!".to_string()),
+        None => return Err("Invalid amount of arguments!".to_string()),
+    };
 
-                state = match args[0] {
-                    "startpos" => ChessState::from_fen("rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 1").unwrap(),
-                    "fen" => {
-                        if args.len() < 2 {
-                            println!("Missing fen!");
-                            continue;
-                        }
+    // Play the moves one by one so the position keeps its history for repetition detection
+    for (i, m) in moves.iter().enumerate() {
+        match state.get_move(m) {
+            Some(mv) => state.make_move(&mv),
+            None => {
+                let warning = format!("Illegal move {} at index {}, ignoring it and all following moves", m, i);
+                return Ok((state, Some(warning)));
+            }
+        }
+    }
 
-                        let fen = args[1..].join(" ");
+    Ok((state, None))
+}
 
-                        match ChessState::from_fen(fen.as_str()) {
-                            Ok(p) => p,
-                            Err(err) => {
-                                println!("{}", err);
-                                continue;
-                            }
+/// A search running in the background while we keep listening to the GUI
+struct SearchThread {
+    signals: Arc<SearchSignals>,
+    handle: JoinHandle<()>,
+}
+
+impl SearchThread {
+    fn start(mut state: ChessState, limits: SearchLimits, options: EngineOptions, transposition_table: Arc<TranspositionTable>) -> Self {
+        let signals = Arc::new(SearchSignals::default());
+        signals.ponder.store(limits.ponder, Ordering::Relaxed);
+
+        let handle = thread::spawn({
+            let signals = signals.clone();
+            move || {
+                let (_, moves) = state.eval(&limits, &options, &transposition_table, &signals);
+
+                // When pondering or in infinite mode the bestmove may only be sent once the GUI told us to stop
+                while (limits.infinite || signals.ponder.load(Ordering::Relaxed)) && !signals.stop.load(Ordering::Relaxed) {
+                    thread::park();
+                }
+
+                let best_move = match moves.first() {
+                    Some(best_move) => Some(*best_move),
+                    // Searches with depth 0 or mate 0 don't look at any move, then any legal move is still better than none
+                    None if limits.depth == Some(0) || limits.mate == Some(0) => state.gen_moves().first().copied(),
+                    // Otherwise only a mated or stalemated side has nothing to play
+                    None => {
+                        if !state.gen_moves().is_empty() {
+                            println!("info string The search returned no move");
                         }
+                        None
                     }
-                    _ => {
-                        println!("Invalid argument!");
+                };
+                match (best_move, moves.get(1)) {
+                    (Some(best_move), Some(ponder_move)) => println!("bestmove {} ponder {}", best_move, ponder_move),
+                    (Some(best_move), None) => println!("bestmove {}", best_move),
+                    (None, _) => println!("bestmove 0000"),
+                }
+            }
+        });
+
+        Self { signals, handle }
+    }
+
+    /// The opponent played the move we were pondering on, so continue with the normal time limits
+    fn ponderhit(&self) {
+        self.signals.ponder.store(false, Ordering::Relaxed);
+        self.handle.thread().unpark();
+    }
+
+    /// Stops the search and waits until it has sent its bestmove
+    fn stop(self) {
+        self.signals.stop.store(true, Ordering::Relaxed);
+        self.handle.thread().unpark();
+        self.handle.join().unwrap();
+    }
+}
+
+const ENGINE_NAME: &str = "Stupid chess engine";
+const ENGINE_AUTHOR: &str = "Jan";
+
+/// All commands understood by the engine, the standard UCI ones followed by our own debugging extensions
+const COMMANDS: [&str; 15] = [
+    "uci",
+    "debug",
+    "isready",
+    "setoption",
+    "ucinewgame",
+    "position",
+    "go",
+    "stop",
+    "ponderhit",
+    "quit",
+    "d",
+    "m",
+    "perft",
+    "eval",
+    "gob",
+];
+
+const STARTPOS_FEN: &str = "rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 1";
+
+fn main() {
+    println!("{} by {}", ENGINE_NAME, ENGINE_AUTHOR);
+
+    let mut options = EngineOptions::default();
+
+    if let Err(err) = Book::load(&options.book_file) {
+        println!("info string Failed to load book: {}", err);
+    }
+
+    let mut transposition_table = Arc::new(TranspositionTable::new(options.hash_mb));
+
+    let mut debug = false;
+    let mut state = ChessState::from_fen(STARTPOS_FEN).unwrap();
+    let mut search_thread: Option<SearchThread> = None;
+    loop {
+        let mut line = String::new();
+        match stdin().read_line(&mut line) {
+            // The GUI closed our input, there is nothing left to do
+            Ok(0) | Err(_) => {
+                if let Some(search_thread) = search_thread.take() {
+                    search_thread.stop();
+                }
+                break;
+            }
+            Ok(_) => (),
+        }
+
+        let (cmd, args) = match split_command(&line) {
+            Some(command) => command,
+            None => {
+                if debug && !line.trim().is_empty() {
+                    println!("info string Unknown command: {}", line.trim());
+                }
+                continue;
+            }
+        };
+
+        // Everything except these commands has to wait for a running search to end
+        if !["uci", "debug", "isready", "ponderhit"].contains(&cmd) {
+            if let Some(search_thread) = search_thread.take() {
+                search_thread.stop();
+            }
+        }
+
+        match cmd {
+            "uci" => {
+                println!("id name {} {}", ENGINE_NAME, env!("CARGO_PKG_VERSION"));
+                println!("id author {}", ENGINE_AUTHOR);
+                EngineOptions::print_uci();
+                println!("uciok");
+            }
+            "debug" => match args.first() {
+                Some(&"on") => debug = true,
+                Some(&"off") => debug = false,
+                _ => println!("info string Expected on or off"),
+            },
+            "isready" => println!("readyok"),
+            "setoption" => {
+                let (name, value) = match parse_setoption(&args) {
+                    Ok(option) => option,
+                    Err(err) => {
+                        println!("info string {}", err);
                         continue;
                     }
+                };
+
+                // No search is running anymore, so we are the only owner of the table
+                let transposition_table = Arc::get_mut(&mut transposition_table).unwrap();
+                if let Err(err) = options.set(&name, value.as_deref(), transposition_table) {
+                    println!("info string {}", err);
+                }
+            }
+            "ucinewgame" => state = ChessState::from_fen(STARTPOS_FEN).unwrap(),
+            "stop" => (),
+            "ponderhit" => {
+                if let Some(search_thread) = &search_thread {
+                    search_thread.ponderhit();
                 }
             }
+            "quit" => break,
+            "position" => match parse_position(&args) {
+                Ok((new_state, warning)) => {
+                    if let Some(warning) = warning {
+                        println!("info string {}", warning);
+                    }
+                    state = new_state;
+                }
+                Err(err) => println!("{}", err),
+            },
             "d" => {
                 println!("{}", state);
             }
@@ -156,75 +359,109 @@ fn main() {
                     }
                 };
 
-                println!("{}", state.eval(Some(depth), None).0);
+                let limits = SearchLimits {
+                    depth: Some(depth),
+                    ..Default::default()
+                };
+                println!("{}", state.eval(&limits, &options, &transposition_table, &SearchSignals::default()).0);
             }
-            "go" => {
-                if args.len() != 1 {
-                    println!("Invalid amount of arguments!");
-                    continue;
-                }
-
-                let depth: u32 = match args[0].parse() {
-                    Ok(depth) => depth,
-                    _ => {
-                        println!("Invalid depth!");
+            "go" | "gob" => {
+                let limits = match parse_go(&args) {
+                    Ok(limits) => limits,
+                    Err(err) => {
+                        println!("info string {}", err);
                         continue;
                     }
                 };
 
-                let (eval, moves) = state.eval(Some(depth), None);
-                println!("{} {}", eval, fmt_moves(&moves));
-                println!("bestmove {}", moves[0]);
-            }
-            "gob" => {
-                if args.len() != 1 {
-                    println!("Invalid amount of arguments!");
-                    continue;
-                }
-
-                let depth: u32 = match args[0].parse() {
-                    Ok(depth) => depth,
-                    _ => {
-                        println!("Invalid depth!");
+                // There is no point in looking at the book when we have to keep searching until told to stop or look for a mate
+                if (cmd == "gob" || options.own_book) && !limits.infinite && !limits.ponder && limits.mate.is_none() {
+                    if let Some(book_move) = state.find_book_move() {
+                        println!("info string Found book move");
+                        println!("bestmove {}", book_move);
                         continue;
                     }
-                };
-
-                if let Some(book_move) = state.find_book_move() {
-                    println!("Found book move");
-                    println!("bestmove {}", book_move);
-                    continue;
                 }
 
-                let (eval, moves) = state.eval(Some(depth), None);
-                println!("{} {}", eval, fmt_moves(&moves));
-                println!("bestmove {}", moves[0]);
+                search_thread = Some(SearchThread::start(state.clone(), limits, options.clone(), transposition_table.clone()));
             }
-            "gotime" => {
-                if args.len() != 1 {
-                    println!("Invalid amount of arguments!");
-                    continue;
-                }
+            _ => unreachable!(),
+        }
+    }
+}
 
-                let seconds: u64 = match args[0].parse() {
-                    Ok(time) => time,
-                    _ => {
-                        println!("Invalid time!");
-                        continue;
-                    }
-                };
+#[cfg(test)]
+mod tests {
+    use super::*;
 
-                if let Some(book_move) = state.find_book_move() {
-                    println!("Found book move");
-                    println!("bestmove {}", book_move);
-                    continue;
-                }
+    #[test]
+    fn split_command_test() {
+        assert_eq!(split_command("isready\n"), Some(("isready", vec![])));
+        assert_eq!(split_command("  go   depth 5 "), Some(("go", vec!["depth", "5"])));
+        // Unknown tokens in front of the command are skipped
+        assert_eq!(split_command("joho debug on"), Some(("debug", vec!["on"])));
+        assert_eq!(split_command("foo bar"), None);
+        assert_eq!(split_command(""), None);
+    }
 
-                let (eval, moves) = state.eval(None, Some(Duration::from_secs(seconds)));
-                println!("{} {}", eval, fmt_moves(&moves));
-                println!("bestmove {}", moves[0]);
-            }
-            _ => println!("Unknown command!"),
-        }
+    #[test]
+    fn parse_go_test() {
+        let limits = parse_go(&["wtime", "-1500", "btime", "2000", "winc", "100", "movestogo", "20"]).unwrap();
+        // A negative clock means the time is already used up
+        assert_eq!(limits.wtime, Some(Duration::ZERO));
+        assert_eq!(limits.btime, Some(Duration::from_millis(2000)));
+        assert_eq!(limits.winc, Duration::from_millis(100));
+        assert_eq!(limits.movestogo, Some(20));
+
+        // Unknown arguments are ignored
+        let limits = parse_go(&["foo", "depth", "7", "infinite"]).unwrap();
+        assert_eq!(limits.depth, Some(7));
+        assert!(limits.infinite);
+
+        assert!(parse_go(&["depth"]).is_err());
+        assert!(parse_go(&["nodes", "many"]).is_err());
+        assert!(parse_go(&["winc", "-5"]).is_err());
+    }
+
+    #[test]
+    fn parse_setoption_test() {
+        assert_eq!(
+            parse_setoption(&["name", "Threads", "value", "4"]),
+            Ok(("Threads".to_string(), Some("4".to_string())))
+        );
+        assert_eq!(
+            parse_setoption(&["name", "Move", "Overhead", "value", "100"]),
+            Ok(("Move Overhead".to_string(), Some("100".to_string())))
+        );
+        assert_eq!(
+            parse_setoption(&["name", "BookFile", "value", "my", "book.bin"]),
+            Ok(("BookFile".to_string(), Some("my book.bin".to_string())))
+        );
+        assert_eq!(parse_setoption(&["name", "Clear", "Hash"]), Ok(("Clear Hash".to_string(), None)));
+        assert!(parse_setoption(&["Threads", "value", "4"]).is_err());
+        assert!(parse_setoption(&[]).is_err());
+    }
+
+    #[test]
+    fn parse_position_test() {
+        let expected = ChessState::from_fen("rnbqkbnr/pppp1ppp/8/4p3/4P3/8/PPPP1PPP/RNBQKBNR w KQkq e6 0 2").unwrap();
+
+        let (state, warning) = parse_position(&["startpos", "moves", "e2e4", "e7e5"]).unwrap();
+        assert_eq!(state.to_string(), expected.to_string());
+        assert_eq!(warning, None);
+
+        // Moves starting from an illegal one are ignored
+        let (state, warning) = parse_position(&["startpos", "moves", "e2e4", "e7e5", "e1e3", "g1f3"]).unwrap();
+        assert_eq!(state.to_string(), expected.to_string());
+        assert!(warning.unwrap().contains("e1e3 at index 2"));
+
+        let fen = ["fen", "rnbqkbnr/pppp1ppp/8/4p3/4P3/8/PPPP1PPP/RNBQKBNR", "w", "KQkq", "e6", "0", "2"];
+        let (state, _) = parse_position(&fen).unwrap();
+        assert_eq!(state.to_string(), expected.to_string());
+
+        assert!(parse_position(&[]).is_err());
+        assert!(parse_position(&["fen"]).is_err());
+        assert!(parse_position(&["fen", "not", "a", "fen"]).is_err());
+        assert!(parse_position(&["somewhere", "moves", "e2e4"]).is_err());
     }
 }
@@ -76,7 +76,7 @@ impl Book {
     }
 
     pub fn get(key: u64) -> Option<String> {
-        let book = unsafe { BOOK.as_ref() }.unwrap().read().unwrap();
+        let book = unsafe { BOOK.as_ref() }?.read().unwrap();
 
         book.entries.binary_search_by_key(&key, |e| e.key).ok().map(|i| book.entries[i].move_.clone())
     }
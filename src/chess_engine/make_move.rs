@@ -22,7 +22,44 @@ pub struct Unmove {
     en_passant: bool,
 }
 
+/// What is needed to take back a null move
+#[derive(Clone, Copy)]
+pub struct NullUnmove {
+    old_en_passant_target: Option<u8>,
+    old_halfmove_clock: u8,
+    old_hash: u64,
+    old_is_draw_by_repetition: bool,
+}
+
 impl ChessState {
+    /// Passes the turn to the opponent without moving, this must not be done while in check
+    ///
+    /// The resulting position is not counted for repetitions.
+    pub fn make_null_move(&mut self) -> NullUnmove {
+        let unmove = NullUnmove {
+            old_en_passant_target: self.en_passant_target,
+            old_halfmove_clock: self.halfmove_clock,
+            old_hash: self.hash,
+            old_is_draw_by_repetition: self.is_draw_by_repetition,
+        };
+
+        self.hash = Zobrist::null_move_update(self.hash, self);
+        self.en_passant_target = None;
+        self.halfmove_clock += 1;
+        self.is_draw_by_repetition = false;
+        self.turn = self.turn.opposite();
+
+        unmove
+    }
+
+    pub fn unmake_null_move(&mut self, unmove: NullUnmove) {
+        self.turn = self.turn.opposite();
+        self.en_passant_target = unmove.old_en_passant_target;
+        self.halfmove_clock = unmove.old_halfmove_clock;
+        self.hash = unmove.old_hash;
+        self.is_draw_by_repetition = unmove.old_is_draw_by_repetition;
+    }
+
     pub fn make_move(&mut self, m: &Move) {
         self.unmove_stack.push(Unmove {
             old_en_passant_target: self.en_passant_target,
@@ -139,6 +176,11 @@ impl ChessState {
         self.increment_current_position_counter_and_update_draw_by_repetition();
     }
 
+    /// The from and to square of the last move made on this state, null moves are not included
+    pub fn last_move_squares(&self) -> Option<(u8, u8)> {
+        self.unmove_stack.last().map(|unmove| (unmove.from, unmove.to))
+    }
+
     pub fn unmake_last_move(&mut self) {
         self.decrement_current_position_counter();
 
@@ -387,6 +429,23 @@ mod tests {
         );
     }
 
+    #[test]
+    fn null_move_test() {
+        let fen = "r3k2r/pppp1ppp/8/8/3Pp3/8/PPP1PPPP/R3K2R b KQkq d3 0 3";
+        let mut state = ChessState::from_fen(fen).unwrap();
+
+        let unmove = state.make_null_move();
+        assert_eq!(
+            state.hash,
+            ChessState::from_fen("r3k2r/pppp1ppp/8/8/3Pp3/8/PPP1PPPP/R3K2R w KQkq - 1 3").unwrap().hash
+        );
+        assert!(find_move(&state.gen_moves(), "d4d5").is_some());
+
+        state.unmake_null_move(unmove);
+        assert_eq!(state.hash, ChessState::from_fen(fen).unwrap().hash);
+        assert!(find_move(&state.gen_moves(), "e4d3").is_some());
+    }
+
     #[test]
     fn draw_by_repetition_test() {
         let mut state = ChessState::from_fen("rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 1").unwrap();
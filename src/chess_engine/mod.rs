@@ -10,8 +10,13 @@ pub mod display;
 pub mod fen;
 pub mod gen_moves;
 pub mod make_move;
+pub mod move_ordering;
+pub mod options;
 pub mod search;
+pub mod search_params;
+pub mod see;
 pub mod static_eval;
+pub mod time_manager;
 pub mod transposition_table;
 pub mod zobrist;
 
@@ -0,0 +1,140 @@
+use super::{gen_moves::Move, ChessState, PieceColor, PieceColorArray};
+
+/// The best move from the transposition table is always tried first
+pub const TT_MOVE_SCORE: i32 = i32::MAX;
+/// Captures and promotions that don't lose material are always tried before quiet moves
+const CAPTURE_SCORE: i32 = 1 << 24;
+/// Captures that lose material according to the static exchange evaluation are tried last
+const BAD_CAPTURE_SCORE: i32 = -(1 << 24);
+const KILLER_SCORE: i32 = 1 << 22;
+const COUNTERMOVE_SCORE: i32 = 1 << 21;
+/// History scores are kept below this so they never outrank killers and countermoves
+const MAX_HISTORY: i32 = 1 << 20;
+
+/// Tables of quiet moves that caused beta cutoffs, used to try the most promising quiet moves first
+pub struct MoveOrdering {
+    /// The last two quiet moves that caused a beta cutoff at each ply
+    killers: Vec<[Option<Move>; 2]>,
+    /// Butterfly table of how often a quiet move caused a beta cutoff, indexed by color, from and to square
+    history: PieceColorArray<[[i32; 64]; 64]>,
+    /// The quiet move that last refuted a move, indexed by the from and to square of the refuted move
+    countermoves: [[Option<Move>; 64]; 64],
+}
+
+fn is_quiet(m: &Move) -> bool {
+    m.capture.is_none() && m.promote_to.is_none()
+}
+
+impl Default for MoveOrdering {
+    fn default() -> Self {
+        Self {
+            killers: Vec::new(),
+            history: PieceColorArray([[[0; 64]; 64]; 2]),
+            countermoves: [[None; 64]; 64],
+        }
+    }
+}
+
+impl MoveOrdering {
+    /// Scores a move for the move ordering, moves with higher scores are tried first
+    pub fn score(&self, state: &ChessState, m: &Move, ply: u32, previous_move: Option<(u8, u8)>) -> i32 {
+        if !is_quiet(m) {
+            return if state.see(m) >= 0 {
+                CAPTURE_SCORE + m.static_eval()
+            } else {
+                BAD_CAPTURE_SCORE + m.static_eval()
+            };
+        }
+
+        if let Some(killers) = self.killers.get(ply as usize) {
+            if killers[0] == Some(*m) {
+                return KILLER_SCORE + 1;
+            }
+            if killers[1] == Some(*m) {
+                return KILLER_SCORE;
+            }
+        }
+
+        if let Some((from, to)) = previous_move
+            && self.countermoves[from as usize][to as usize] == Some(*m)
+        {
+            return COUNTERMOVE_SCORE;
+        }
+
+        self.history[state.turn][m.from as usize][m.to as usize]
+    }
+
+    /// Rewards a move that caused a beta cutoff and punishes the quiet moves that were tried before it
+    pub fn update(&mut self, m: &Move, turn: PieceColor, ply: u32, previous_move: Option<(u8, u8)>, depth: i32, tried_quiets: &[Move]) {
+        if !is_quiet(m) {
+            return;
+        }
+
+        if self.killers.len() <= ply as usize {
+            self.killers.resize(ply as usize + 1, [None, None]);
+        }
+        let killers = &mut self.killers[ply as usize];
+        if killers[0] != Some(*m) {
+            killers[1] = killers[0];
+            killers[0] = Some(*m);
+        }
+
+        if let Some((from, to)) = previous_move {
+            self.countermoves[from as usize][to as usize] = Some(*m);
+        }
+
+        // Deeper cutoffs are more reliable, so they count more
+        let bonus = depth * depth;
+        self.add_history(m, turn, bonus);
+        for tried in tried_quiets.iter().filter(|tried| *tried != m) {
+            self.add_history(tried, turn, -bonus);
+        }
+    }
+
+    fn add_history(&mut self, m: &Move, turn: PieceColor, bonus: i32) {
+        let history = &mut self.history[turn];
+        history[m.from as usize][m.to as usize] += bonus;
+
+        // Halve all scores once one gets too large, this also lets old cutoffs fade out
+        if history[m.from as usize][m.to as usize].abs() >= MAX_HISTORY {
+            for score in history.iter_mut().flatten() {
+                *score /= 2;
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::chess_engine::ChessState;
+
+    #[test]
+    fn move_ordering_test() {
+        let mut state = ChessState::from_fen("r3k2r/pppq1pp1/2n2n1p/3pp3/3PP3/2N2N2/PPPQ1PPP/R3K2R w KQkq - 0 1").unwrap();
+        let capture = state.get_move("e4d5").unwrap();
+        let bad_capture = state.get_move("d2h6").unwrap();
+        let killer = state.get_move("a2a3").unwrap();
+        let countermove = state.get_move("h2h3").unwrap();
+        let history = state.get_move("b2b3").unwrap();
+        let other = state.get_move("g2g3").unwrap();
+        let turn = PieceColor::White;
+        let previous_move = Some((51, 35));
+
+        let mut ordering = MoveOrdering::default();
+        ordering.update(&history, turn, 5, None, 4, &[other]);
+        ordering.update(&countermove, turn, 5, previous_move, 1, &[]);
+        ordering.update(&killer, turn, 3, None, 1, &[]);
+
+        let scores = [capture, killer, countermove, history, other, bad_capture].map(|m| ordering.score(&state, &m, 3, previous_move));
+        assert!(scores.windows(2).all(|w| w[0] > w[1]), "{:?}", scores);
+        // Not even a good capture comes before the move from the transposition table
+        assert!(scores[0] < TT_MOVE_SCORE);
+
+        // Killers are only used at the ply where they caused the cutoff
+        assert!(ordering.score(&state, &killer, 4, None) < ordering.score(&state, &history, 4, None));
+        // Captures don't go into the tables
+        ordering.update(&capture, turn, 3, None, 10, &[]);
+        assert_eq!(ordering.score(&state, &killer, 3, None), KILLER_SCORE + 1);
+    }
+}
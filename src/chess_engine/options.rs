@@ -0,0 +1,158 @@
+use std::{fmt::Display, str::FromStr, time::Duration};
+
+use super::{book::Book, search_params::SearchParams, transposition_table::TranspositionTable};
+
+/// The settings of the engine that can be changed by the GUI through setoption
+#[derive(Clone, Debug)]
+pub struct EngineOptions {
+    pub hash_mb: usize,
+    pub threads: usize,
+    pub own_book: bool,
+    pub book_file: String,
+    pub multi_pv: usize,
+    pub move_overhead: Duration,
+    pub ponder: bool,
+    /// The maximum amount of centipawns of noise added to the evaluation, 0 keeps the search deterministic
+    pub variety: i32,
+    /// The same seed and variety always give the same noise
+    pub seed: u64,
+    /// Changeable through UCI for tuning, see `SearchParams::UCI_OPTIONS`
+    pub search_params: SearchParams,
+}
+
+impl Default for EngineOptions {
+    fn default() -> Self {
+        Self {
+            hash_mb: 64,
+            threads: 1,
+            own_book: true,
+            book_file: "book.bin".to_string(),
+            multi_pv: 1,
+            move_overhead: Duration::from_millis(30),
+            ponder: false,
+            variety: 0,
+            seed: 0,
+            search_params: SearchParams::default(),
+        }
+    }
+}
+
+const MAX_HASH_MB: usize = 65536;
+const MAX_THREADS: usize = 256;
+const MAX_MULTI_PV: usize = 256;
+const MAX_MOVE_OVERHEAD_MS: u64 = 5000;
+const MAX_VARIETY: i32 = 100;
+/// GUIs read spin values as signed 32 bit numbers
+const MAX_SEED: u64 = i32::MAX as u64;
+
+fn parse_spin<T: FromStr + PartialOrd + Display>(value: Option<&str>, min: T, max: T) -> Result<T, String> {
+    match value.and_then(|v| v.parse().ok()) {
+        Some(v) if v >= min && v <= max => Ok(v),
+        _ => Err(format!("Expected a number between {} and {}", min, max)),
+    }
+}
+
+fn parse_check(value: Option<&str>) -> Result<bool, String> {
+    match value {
+        Some("true") => Ok(true),
+        Some("false") => Ok(false),
+        _ => Err("Expected true or false".to_string()),
+    }
+}
+
+impl EngineOptions {
+    /// Prints the option lines that are sent in response to the uci command
+    pub fn print_uci() {
+        let defaults = Self::default();
+
+        println!("option name Hash type spin default {} min 1 max {}", defaults.hash_mb, MAX_HASH_MB);
+        println!("option name Threads type spin default {} min 1 max {}", defaults.threads, MAX_THREADS);
+        println!("option name OwnBook type check default {}", defaults.own_book);
+        println!("option name BookFile type string default {}", defaults.book_file);
+        println!("option name MultiPV type spin default {} min 1 max {}", defaults.multi_pv, MAX_MULTI_PV);
+        println!(
+            "option name Move Overhead type spin default {} min 0 max {}",
+            defaults.move_overhead.as_millis(),
+            MAX_MOVE_OVERHEAD_MS
+        );
+        println!("option name Clear Hash type button");
+        println!("option name Ponder type check default {}", defaults.ponder);
+        println!("option name Variety type spin default {} min 0 max {}", defaults.variety, MAX_VARIETY);
+        println!("option name Seed type spin default {} min 0 max {}", defaults.seed, MAX_SEED);
+        for (name, min, max) in SearchParams::UCI_OPTIONS {
+            let default = defaults.search_params.uci_value(name).unwrap();
+            println!("option name {} type spin default {} min {} max {}", name, default, min, max);
+        }
+    }
+
+    /// Sets an option by its UCI name and applies it to the engine, option names are case insensitive
+    pub fn set(&mut self, name: &str, value: Option<&str>, transposition_table: &mut TranspositionTable) -> Result<(), String> {
+        match name.to_lowercase().as_str() {
+            "hash" => {
+                self.hash_mb = parse_spin(value, 1, MAX_HASH_MB)?;
+                transposition_table.resize(self.hash_mb);
+            }
+            "threads" => self.threads = parse_spin(value, 1, MAX_THREADS)?,
+            "ownbook" => self.own_book = parse_check(value)?,
+            "bookfile" => {
+                let book_file = value.ok_or("Expected a path")?.to_string();
+                Book::load(&book_file).map_err(|err| format!("Failed to load book: {}", err))?;
+                self.book_file = book_file;
+            }
+            "multipv" => self.multi_pv = parse_spin(value, 1, MAX_MULTI_PV)?,
+            "move overhead" => self.move_overhead = Duration::from_millis(parse_spin(value, 0, MAX_MOVE_OVERHEAD_MS)?),
+            "clear hash" => transposition_table.clear(),
+            "ponder" => self.ponder = parse_check(value)?,
+            "variety" => self.variety = parse_spin(value, 0, MAX_VARIETY)?,
+            "seed" => self.seed = parse_spin(value, 0, MAX_SEED)?,
+            lowercase_name => match SearchParams::UCI_OPTIONS.iter().find(|(param, _, _)| param.to_lowercase() == lowercase_name) {
+                Some(&(param, min, max)) => {
+                    self.search_params.set_uci_value(param, parse_spin(value, min, max)?);
+                }
+                None => return Err(format!("Unknown option {}", name)),
+            },
+        }
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn set_option_test() {
+        let mut options = EngineOptions::default();
+        let mut transposition_table = TranspositionTable::new(1);
+
+        assert!(options.set("threads", Some("8"), &mut transposition_table).is_ok());
+        assert_eq!(options.threads, 8);
+
+        assert!(options.set("Move Overhead", Some("100"), &mut transposition_table).is_ok());
+        assert_eq!(options.move_overhead, Duration::from_millis(100));
+
+        assert!(options.set("OwnBook", Some("false"), &mut transposition_table).is_ok());
+        assert!(!options.own_book);
+
+        assert!(options.set("Threads", Some("0"), &mut transposition_table).is_err());
+        assert!(options.set("MultiPV", None, &mut transposition_table).is_err());
+        assert!(options.set("Ponder", Some("yes"), &mut transposition_table).is_err());
+        assert!(options.set("Foo", Some("1"), &mut transposition_table).is_err());
+        assert_eq!(options.threads, 8);
+
+        assert!(options.set("Variety", Some("20"), &mut transposition_table).is_ok());
+        assert_eq!(options.variety, 20);
+        assert!(options.set("Seed", Some("-1"), &mut transposition_table).is_err());
+        assert!(options.set("Seed", Some("4294967295"), &mut transposition_table).is_err());
+
+        assert!(options.set("LmrBase", Some("100"), &mut transposition_table).is_ok());
+        assert_eq!(options.search_params.lmr_base, 1.0);
+        assert!(options.set("rfpmargin", Some("150"), &mut transposition_table).is_ok());
+        assert_eq!(options.search_params.rfp_margin, 150);
+        assert!(options.set("IidReduction", Some("0"), &mut transposition_table).is_err());
+
+        assert!(options.set("Hash", Some("2"), &mut transposition_table).is_ok());
+        assert_eq!(options.hash_mb, 2);
+    }
+}
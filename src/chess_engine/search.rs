@@ -1,20 +1,85 @@
-use std::time::{Duration, Instant};
+use std::{
+    cell::{Cell, RefCell},
+    cmp::Reverse,
+    sync::atomic::{AtomicBool, AtomicU64, Ordering},
+    time::{Duration, Instant},
+};
+
+use rand::{rngs::StdRng, RngCore, SeedableRng};
+use rayon::ThreadPoolBuilder;
 
 use super::{
     book::Book,
     gen_moves::Move,
+    move_ordering::{MoveOrdering, TT_MOVE_SCORE},
+    options::EngineOptions,
+    search_params::{SearchParams, REDUCTION_TABLE_SIZE},
+    time_manager::{SearchLimits, TimeManager},
     transposition_table::{TranspositionEntry, TranspositionEntryType, TranspositionTable},
     ChessState, PieceColorArray,
 };
 
 const CHECKMATE_EVAL: i32 = 1000000;
-const MAX_SEARCH_DURATION: Duration = Duration::from_secs(31536000);
 const MAX_DEPTH: u32 = 200;
+/// Scores above this are mates, the difference to CHECKMATE_EVAL is the amount of plies until the mate
+const MATE_THRESHOLD: i32 = CHECKMATE_EVAL - 1000;
+/// Null move pruning is only done with at least this much depth left
+const NULL_MOVE_MIN_DEPTH: i32 = 3;
+/// How much shallower the null move is searched, it grows further with the depth
+const NULL_MOVE_REDUCTION: i32 = 2;
+/// Singular extensions need a transposition table entry from a search at most this much shallower
+const SINGULAR_TT_DEPTH_MARGIN: i32 = 3;
+/// After this time we tell the GUI which root move we are currently searching
+const CURRMOVE_INFO_DELAY: Duration = Duration::from_secs(3);
 
 const _TURN_MULT: PieceColorArray<i32> = PieceColorArray([1, -1]);
 
-struct Search {
-    search_end_time: Instant,
+/// Flags through which a running search is controlled from another thread
+#[derive(Default)]
+pub struct SearchSignals {
+    /// Set from the outside (or by the search once the time is up) to end the search as soon as possible
+    pub stop: AtomicBool,
+    /// While set the search ignores its time limits because we are thinking on the opponent's time
+    pub ponder: AtomicBool,
+}
+
+struct Search<'a> {
+    time_manager: TimeManager,
+    signals: &'a SearchSignals,
+    transposition_table: &'a TranspositionTable,
+    params: &'a SearchParams,
+    /// Maximum noise added to the evaluation and the key that decides the noise of each position
+    variety: i32,
+    variety_key: u64,
+    /// Late move reductions indexed by depth and move number
+    reductions: [[i32; REDUCTION_TABLE_SIZE]; REDUCTION_TABLE_SIZE],
+    /// Only the main thread reports its search and decides when to stop, helper threads run until they are told to stop
+    is_main: bool,
+    /// The search may only be aborted once we have found a move to play
+    can_abort: bool,
+    /// When solving for a mate, the amount of plies the mate has to be found in
+    ///
+    /// All pruning and reductions are disabled then, so a mate score is a proof and a missing one a refutation.
+    mate_plies: Option<u32>,
+    /// The search stops once all threads together have searched this many nodes
+    node_limit: Option<u64>,
+    /// The amount of best root moves that get an exact score and their own line
+    multi_pv: usize,
+
+    /// Shared by all threads of a search
+    nodes: &'a AtomicU64,
+    /// The highest ply reached in the current iteration
+    seldepth: Cell<u32>,
+    /// Triangular table of principal variations, the entry at a ply holds the best line found from the node at that ply
+    pv_table: RefCell<Vec<Vec<Move>>>,
+    /// The ply at which the current line made its last null move, two null moves in a row would prove nothing
+    null_move_ply: Cell<Option<u32>>,
+    /// The ply of a singular extension verification search and the move it leaves out
+    excluded_move: Cell<Option<(u32, Move)>>,
+    /// The root moves of the lines already found in this iteration, they are left out when searching for the next best line
+    root_excluded: RefCell<Vec<Move>>,
+    /// Killer, history and countermove tables of this thread
+    move_ordering: RefCell<MoveOrdering>,
 }
 
 #[derive(PartialEq, Eq)]
@@ -25,26 +90,163 @@ enum NodeType {
     Quiesce,
 }
 
-impl Search {
+impl<'a> Search<'a> {
+    fn new(
+        time_manager: TimeManager,
+        signals: &'a SearchSignals,
+        transposition_table: &'a TranspositionTable,
+        options: &'a EngineOptions,
+        nodes: &'a AtomicU64,
+    ) -> Self {
+        Self {
+            time_manager,
+            signals,
+            transposition_table,
+            params: &options.search_params,
+            variety: options.variety,
+            variety_key: StdRng::seed_from_u64(options.seed).next_u64(),
+            reductions: options.search_params.reduction_table(),
+            is_main: true,
+            can_abort: false,
+            mate_plies: None,
+            node_limit: None,
+            multi_pv: options.multi_pv,
+            nodes,
+            seldepth: Cell::new(0),
+            pv_table: RefCell::new(Vec::new()),
+            null_move_ply: Cell::new(None),
+            excluded_move: Cell::new(None),
+            root_excluded: RefCell::new(Vec::new()),
+            move_ordering: RefCell::new(MoveOrdering::default()),
+        }
+    }
+
+    fn clear_pv(&self, ply: u32) {
+        let mut pv_table = self.pv_table.borrow_mut();
+        if pv_table.len() <= ply as usize + 1 {
+            pv_table.resize(ply as usize + 2, Vec::new());
+        }
+        pv_table[ply as usize].clear();
+    }
+
+    /// Sets the pv at this ply to the new best move followed by the pv of the child node
+    fn update_pv(&self, ply: u32, m: Move) {
+        let mut pv_table = self.pv_table.borrow_mut();
+        let (parent, child) = pv_table.split_at_mut(ply as usize + 1);
+        let pv = &mut parent[ply as usize];
+
+        pv.clear();
+        pv.push(m);
+        pv.extend_from_slice(&child[0]);
+    }
+
+    /// The static evaluation plus the noise of the variety option
+    ///
+    /// The noise only depends on the position and the seed, so the search stays reproducible
+    /// and the evaluations in the transposition table stay consistent.
+    fn evaluate(&self, state: &ChessState) -> i32 {
+        if self.variety == 0 {
+            return state.static_eval();
+        }
+
+        let mixed = (state.hash ^ self.variety_key).wrapping_mul(0x9E3779B97F4A7C15) >> 32;
+        state.static_eval() + (mixed % (2 * self.variety as u64 + 1)) as i32 - self.variety
+    }
+
+    fn should_stop(&self) -> bool {
+        if !self.can_abort {
+            return false;
+        }
+
+        if !self.signals.ponder.load(Ordering::Relaxed) && self.time_manager.hard_limit_reached() {
+            self.signals.stop.store(true, Ordering::Relaxed);
+        }
+
+        self.signals.stop.load(Ordering::Relaxed)
+    }
+
+    /// Counts a node, or stops the search if that would exceed the node limit
+    ///
+    /// The node is reserved in the shared counter in one step, so the limit also holds with several threads.
+    /// Like the time limits the node limit doesn't apply while pondering.
+    fn count_node(&self) -> bool {
+        let node_limit = match self.node_limit {
+            Some(node_limit) if self.can_abort && !self.signals.ponder.load(Ordering::Relaxed) => node_limit,
+            _ => {
+                self.nodes.fetch_add(1, Ordering::Relaxed);
+                return true;
+            }
+        };
+
+        let counted = self
+            .nodes
+            .fetch_update(Ordering::Relaxed, Ordering::Relaxed, |nodes| (nodes < node_limit).then_some(nodes + 1))
+            .is_ok();
+        if !counted {
+            self.signals.stop.store(true, Ordering::Relaxed);
+        }
+
+        counted
+    }
+
+    fn should_start_iteration(&self, last_iteration: Duration) -> bool {
+        self.signals.ponder.load(Ordering::Relaxed) || self.time_manager.should_start_iteration(last_iteration)
+    }
+
     fn search<const NODE_TYPE: NodeType>(&self, state: &mut ChessState, mut alpha: i32, mut beta: i32, depth_left: i32, ply: u32) -> i32 {
+        if !self.count_node() {
+            return 0;
+        }
+
+        self.seldepth.set(self.seldepth.get().max(ply));
+        self.clear_pv(ply);
+
         // Respect draws by the 50 move rule and by threefold repetition
-        if state.halfmove_clock >= 50 || state.is_draw_by_repetition {
+        // The root is searched anyway, the game history may already count as a draw but we still need a move to play
+        if NODE_TYPE != NodeType::Root && (state.halfmove_clock >= 50 || state.is_draw_by_repetition) {
             return 0;
         }
 
+        // Mate distance pruning: not even a mate on the next move can beat a shorter mate that was already found
+        if NODE_TYPE != NodeType::Root {
+            alpha = alpha.max(-CHECKMATE_EVAL + ply as i32);
+            beta = beta.min(CHECKMATE_EVAL - ply as i32 - 1);
+            if alpha >= beta {
+                return alpha;
+            }
+        }
+
+        let in_check = state.check[state.turn];
+
+        // Check extension: search positions in check one ply deeper, so a line never ends right after a check
+        let mut depth_left = if in_check && NODE_TYPE != NodeType::Quiesce && ply < MAX_DEPTH {
+            depth_left + 1
+        } else {
+            depth_left
+        };
+
         // If we have reached the maximum depth do a quiesce search
         // The quiesce search then continues infinitely until no more captures are possible with a depth_left < 0
         if NODE_TYPE != NodeType::Quiesce && depth_left == 0 {
-            return self.search::<{ NodeType::Quiesce }>(state, alpha, beta, depth_left - 1, ply + 1);
+            // This is still the same position, so the ply stays the same for the mate scores in the transposition table
+            return self.search::<{ NodeType::Quiesce }>(state, alpha, beta, depth_left - 1, ply);
         }
 
         let start_alpha = alpha;
         let mut best_move = None;
 
+        // A verification search for a singular extension searches this node again without the transposition table move,
+        // it must neither use nor overwrite the table entry of the full node
+        let excluded_move = match self.excluded_move.get() {
+            Some((excluded_ply, m)) if excluded_ply == ply => Some(m),
+            _ => None,
+        };
+
         // If we are doing a quiesce search perform optimisations such as
         // standing pat and delta pruning
-        if NODE_TYPE == NodeType::Quiesce {
-            let stand_pat = state.static_eval();
+        // In check standing pat is not an option, every evasion has to be searched instead
+        if NODE_TYPE == NodeType::Quiesce && !in_check {
+            let stand_pat = self.evaluate(state);
 
             if stand_pat >= beta {
                 return beta;
@@ -62,16 +264,24 @@ impl Search {
         }
 
         // Check if the current state is in the transposition table
-        if let Some(transposition_entry) = TranspositionTable::get(state.hash) {
-            if transposition_entry.depth >= depth_left {
+        let transposition_entry = if excluded_move.is_none() {
+            self.transposition_table.get(state.hash)
+        } else {
+            None
+        };
+        if let Some(transposition_entry) = transposition_entry {
+            // Cutoffs in pv nodes would cut the pv short, so there the entry is only used for move ordering
+            let is_pv_node = NODE_TYPE == NodeType::PV || NODE_TYPE == NodeType::Root;
+            if !is_pv_node && transposition_entry.depth >= depth_left {
+                let score = score_from_tt(transposition_entry.score, ply);
                 match transposition_entry.entry_type {
-                    TranspositionEntryType::Exact => return transposition_entry.score,
-                    TranspositionEntryType::LowerBound => alpha = i32::max(alpha, transposition_entry.score),
-                    TranspositionEntryType::UpperBound => beta = i32::min(beta, transposition_entry.score),
+                    TranspositionEntryType::Exact => return score,
+                    TranspositionEntryType::LowerBound => alpha = i32::max(alpha, score),
+                    TranspositionEntryType::UpperBound => beta = i32::min(beta, score),
                 }
 
                 if alpha >= beta {
-                    return transposition_entry.score;
+                    return score;
                 }
             }
 
@@ -79,24 +289,128 @@ impl Search {
             best_move = transposition_entry.best_move;
         }
 
+        // The pruning below relies on the static evaluation, which means nothing in check
+        let selective = self.mate_plies.is_none();
+        let prunable = NODE_TYPE == NodeType::Cut && !in_check && excluded_move.is_none() && selective;
+        let eval = if prunable { self.evaluate(state) } else { 0 };
+
+        // Reverse futility pruning: if the static evaluation is far above beta no move will bring it below beta
+        if prunable && depth_left <= self.params.rfp_max_depth && beta < MATE_THRESHOLD && eval - self.params.rfp_margin * depth_left >= beta {
+            return eval;
+        }
+
+        // Razoring: if the static evaluation is far below alpha only captures can save the position,
+        // so check if the quiesce search finds one
+        if prunable && depth_left <= self.params.razor_max_depth && alpha > -MATE_THRESHOLD && eval + self.params.razor_margin * depth_left < alpha {
+            let score = self.search::<{ NodeType::Quiesce }>(state, alpha, beta, -1, ply);
+            if score <= alpha {
+                return score;
+            }
+        }
+
+        // Null move pruning: if we are still above beta after passing the turn the position is good enough for a cutoff
+        // This is impossible in check and unsafe without pieces, where zugzwang positions are common
+        if prunable
+            && depth_left >= NULL_MOVE_MIN_DEPTH
+            && state.has_non_pawn_material(state.turn)
+            && self.null_move_ply.get().is_none_or(|null_move_ply| null_move_ply + 1 != ply)
+            && eval >= beta
+        {
+            let reduction = NULL_MOVE_REDUCTION + depth_left / 6;
+
+            let previous_null_move_ply = self.null_move_ply.replace(Some(ply));
+            let unmove = state.make_null_move();
+            let score = -self.search::<{ NodeType::Cut }>(state, -beta, -beta + 1, (depth_left - 1 - reduction).max(0), ply + 1);
+            state.unmake_null_move(unmove);
+            self.null_move_ply.set(previous_null_move_ply);
+
+            if score >= beta {
+                // A mate found after passing the turn is not proven
+                return if score >= MATE_THRESHOLD { beta } else { score };
+            }
+        }
+
+        // Internal iterative deepening: without a move from the transposition table a shallower search of a pv node
+        // finds a good first move, which saves more in the full search than the shallower search costs
+        // Cut nodes are too many for that, instead they are searched one ply shallower (internal iterative reduction)
+        if best_move.is_none() && excluded_move.is_none() && depth_left >= self.params.iid_min_depth {
+            if NODE_TYPE == NodeType::PV || NODE_TYPE == NodeType::Root {
+                self.search::<NODE_TYPE>(state, alpha, beta, depth_left - self.params.iid_reduction, ply);
+                best_move = self.transposition_table.get(state.hash).and_then(|entry| entry.best_move);
+            } else if NODE_TYPE == NodeType::Cut && selective {
+                depth_left -= 1;
+            }
+        }
+
+        // Singular extensions: if the transposition table move beats every other move by a margin, it is searched one ply deeper
+        // To check this the node is searched without the move at reduced depth against a bound below the move's score
+        let mut singular_move = None;
+        if let Some(entry) = transposition_entry
+            && let Some(tt_move) = entry.best_move
+            && (NODE_TYPE == NodeType::PV || NODE_TYPE == NodeType::Cut)
+            && selective
+            && depth_left >= self.params.singular_min_depth
+            && entry.entry_type != TranspositionEntryType::UpperBound
+            && entry.depth >= depth_left - SINGULAR_TT_DEPTH_MARGIN
+            && entry.score.abs() < MATE_THRESHOLD
+        {
+            let singular_beta = score_from_tt(entry.score, ply) - self.params.singular_margin * depth_left;
+
+            let previous_excluded_move = self.excluded_move.replace(Some((ply, tt_move)));
+            let score = self.search::<{ NodeType::Cut }>(state, singular_beta - 1, singular_beta, (depth_left - 1) / 2, ply);
+            self.excluded_move.set(previous_excluded_move);
+
+            if score < singular_beta {
+                singular_move = Some(tt_move);
+            } else if singular_beta >= beta {
+                // Multi-cut: besides the transposition table move another move beats beta too,
+                // with several moves failing high a cutoff at full depth is almost certain
+                return singular_beta;
+            }
+        }
+
         // Generate pseudo legal moves because we can easily check if a move was legal in the search
         let mut moves = state.gen_pseudo_legal_moves();
 
-        // If we are doing a quiesce search then only look at captures
-        if NODE_TYPE == NodeType::Quiesce {
-            moves.retain(|m| m.capture.is_some());
+        // If we are doing a quiesce search then only look at captures that don't lose material
+        if NODE_TYPE == NodeType::Quiesce && !in_check {
+            moves.retain(|m| m.capture.is_some() && state.see(m) >= 0);
         }
 
+        // The move that led to this node, unless it was a null move
+        let previous_move = match self.null_move_ply.get() {
+            Some(null_move_ply) if null_move_ply + 1 == ply => None,
+            _ => state.last_move_squares(),
+        };
+
         // Sort moves by score and put the best move first
-        moves.sort_by_cached_key(|m| if Some(*m) == best_move { CHECKMATE_EVAL } else { m.static_eval() });
+        let move_ordering = self.move_ordering.borrow();
+        moves.sort_by_cached_key(|m| {
+            if Some(*m) == best_move {
+                TT_MOVE_SCORE
+            } else {
+                move_ordering.score(state, m, ply, previous_move)
+            }
+        });
+        drop(move_ordering);
+
+        // Futility pruning: quiet moves can't raise a static evaluation this far below alpha
+        let futile =
+            prunable && depth_left <= self.params.futility_max_depth && alpha > -MATE_THRESHOLD && eval + self.params.futility_margin * depth_left <= alpha;
 
         let mut had_legal_move = false;
+        let mut move_number = 0;
+        let mut tried_quiets = Vec::new();
         let mut pv = NODE_TYPE == NodeType::PV || NODE_TYPE == NodeType::Root;
         for m in moves.iter().rev() {
-            if Instant::now() >= self.search_end_time {
+            if self.should_stop() {
                 return 0;
             }
 
+            if Some(*m) == excluded_move || (NODE_TYPE == NodeType::Root && self.root_excluded.borrow().contains(m)) {
+                continue;
+            }
+
             state.make_move(m);
             if state.check[state.turn.opposite()] {
                 // Skip illegal moves
@@ -105,6 +419,24 @@ impl Search {
             }
 
             had_legal_move = true;
+            move_number += 1;
+
+            if NODE_TYPE == NodeType::Root && self.is_main && self.time_manager.elapsed() >= CURRMOVE_INFO_DELAY {
+                println!("info depth {} currmove {} currmovenumber {}", depth_left, m, move_number);
+            }
+
+            // Moves that change the material or the check status are never reduced or pruned
+            let gives_check = state.check[state.turn];
+            let quiet = m.capture.is_none() && m.promote_to.is_none() && !in_check && !gives_check;
+
+            // Late move pruning: close to the horizon, quiet moves this late in the move ordering rarely matter
+            let late = prunable && depth_left <= self.params.lmp_max_depth && move_number > self.params.lmp_move_count(depth_left) && alpha > -MATE_THRESHOLD;
+            if quiet && (futile || late) {
+                state.unmake_last_move();
+                continue;
+            }
+
+            let child_depth = if Some(*m) == singular_move { depth_left } else { depth_left - 1 };
 
             let score = if NODE_TYPE == NodeType::Quiesce {
                 -self.search::<{ NodeType::Quiesce }>(state, -beta, -alpha, depth_left - 1, ply + 1)
@@ -112,14 +444,28 @@ impl Search {
                 if pv {
                     pv = false;
                     // The first child node of a pv node is also a pv node
-                    -self.search::<{ NodeType::PV }>(state, -beta, -alpha, depth_left - 1, ply + 1)
+                    -self.search::<{ NodeType::PV }>(state, -beta, -alpha, child_depth, ply + 1)
                 } else {
+                    // Late move reductions: quiet moves late in the move ordering are searched with less depth
+                    let reduction = if selective && quiet && depth_left >= self.params.lmr_min_depth && move_number > self.params.lmr_full_depth_moves {
+                        let depth_index = (depth_left as usize).min(REDUCTION_TABLE_SIZE - 1);
+                        let move_index = (move_number as usize).min(REDUCTION_TABLE_SIZE - 1);
+                        self.reductions[depth_index][move_index].min(depth_left - 1)
+                    } else {
+                        0
+                    };
+
                     // All other nodes are cut nodes
-                    let mut score = -self.search::<{ NodeType::Cut }>(state, -alpha - 1, -alpha, depth_left - 1, ply + 1);
+                    let mut score = -self.search::<{ NodeType::Cut }>(state, -alpha - 1, -alpha, child_depth - reduction, ply + 1);
+
+                    // A reduced move that beats alpha has to prove itself at full depth
+                    if reduction > 0 && score > alpha {
+                        score = -self.search::<{ NodeType::Cut }>(state, -alpha - 1, -alpha, child_depth, ply + 1);
+                    }
 
                     // Do a re-search if the score looks promising
                     if score > alpha && score < beta {
-                        score = -self.search::<{ NodeType::PV }>(state, -beta, -alpha, depth_left - 1, ply + 1);
+                        score = -self.search::<{ NodeType::PV }>(state, -beta, -alpha, child_depth, ply + 1);
                     }
                     score
                 }
@@ -127,26 +473,57 @@ impl Search {
 
             state.unmake_last_move();
 
+            // An aborted child returns 0, which must neither count as its score nor end up in the tables
+            if self.should_stop() {
+                return 0;
+            }
+
             if score >= beta {
                 alpha = score;
+                best_move = Some(*m);
+
+                // The bounds of a pv node can be narrowed by mate distance pruning or aspiration windows,
+                // so it can fail high and the line still has to be kept
+                if NODE_TYPE == NodeType::PV || NODE_TYPE == NodeType::Root {
+                    self.update_pv(ply, *m);
+                }
+
+                if NODE_TYPE != NodeType::Quiesce {
+                    self.move_ordering
+                        .borrow_mut()
+                        .update(m, state.turn, ply, previous_move, depth_left, &tried_quiets);
+                }
                 break;
             }
+            if m.capture.is_none() && m.promote_to.is_none() {
+                tried_quiets.push(*m);
+            }
             if score > alpha {
                 alpha = score;
                 best_move = Some(*m);
+
+                if NODE_TYPE != NodeType::Quiesce {
+                    self.update_pv(ply, *m);
+                }
             }
         }
 
-        // If we are not doing a quiesce search and we have not had any legal moves then
-        // it is either a mate or a stalemate
-        if NODE_TYPE != NodeType::Quiesce && !had_legal_move {
-            return match state.check[state.turn] {
+        // Searches that leave out moves only hold for the remaining moves, so they return before the mate detection and the table store
+        // Without other legal moves the excluded move of a verification search is singular anyway
+        if excluded_move.is_some() || (NODE_TYPE == NodeType::Root && !self.root_excluded.borrow().is_empty()) {
+            return alpha;
+        }
+
+        // If we have not had any legal moves then it is either a mate or a stalemate
+        // The quiesce search only knows this when in check, otherwise it did not look at all moves
+        if !had_legal_move && (NODE_TYPE != NodeType::Quiesce || in_check) {
+            return match in_check {
                 true => -CHECKMATE_EVAL + ply as i32,
                 false => 0,
             };
         }
 
-        TranspositionTable::set(
+        self.transposition_table.set(
             state.hash,
             TranspositionEntry {
                 key: state.hash,
@@ -158,7 +535,7 @@ impl Search {
                     TranspositionEntryType::Exact
                 },
                 depth: depth_left,
-                score: alpha,
+                score: score_to_tt(alpha, ply),
                 best_move,
             },
         );
@@ -166,64 +543,322 @@ impl Search {
         alpha
     }
 
-    /// Extracts the best line of moves from the transposition table
-    fn best_line(&self, state: &ChessState, mut depth: u32) -> Vec<Move> {
-        let mut moves = Vec::new();
+    /// Searches with increasing depth until `max_depth` is reached or the search is stopped
+    ///
+    /// Returns the best lines of the last finished iteration with their scores, best first.
+    fn iterative_deepening(&mut self, state: &mut ChessState, start_depth: u32, max_depth: u32) -> Vec<(i32, Vec<Move>)> {
+        let mut best_res = vec![(self.evaluate(state), Vec::new())];
+
+        let mut depth = start_depth;
+        'iterations: while depth <= max_depth {
+            let iteration_start = Instant::now();
+
+            // MultiPV: every further line is searched without the root moves of the better lines
+            let mut lines: Vec<(i32, Vec<Move>)> = Vec::new();
+            self.root_excluded.borrow_mut().clear();
+            for pv_index in 0..self.multi_pv {
+                let previous_score = best_res.get(pv_index).map(|(score, _)| *score);
+
+                // Aspiration windows: a narrow window around the last score causes more cutoffs,
+                // if the score falls outside of it the window is widened and the iteration is repeated
+                let mut delta = self.params.aspiration_window;
+                let (mut alpha, mut beta) = match previous_score {
+                    Some(score) if depth >= self.params.aspiration_min_depth && score.abs() < MATE_THRESHOLD => {
+                        ((score - delta).max(-CHECKMATE_EVAL), (score + delta).min(CHECKMATE_EVAL))
+                    }
+                    _ => (-CHECKMATE_EVAL, CHECKMATE_EVAL),
+                };
+
+                let score = loop {
+                    self.seldepth.set(0);
+                    let score = self.search::<{ NodeType::Root }>(state, alpha, beta, depth as i32, 0);
+
+                    if self.should_stop() {
+                        break 'iterations;
+                    }
+
+                    let bound = if score <= alpha && alpha > -CHECKMATE_EVAL {
+                        alpha = (score - delta).max(-CHECKMATE_EVAL);
+                        TranspositionEntryType::UpperBound
+                    } else if score >= beta && beta < CHECKMATE_EVAL {
+                        beta = (score + delta).min(CHECKMATE_EVAL);
+                        TranspositionEntryType::LowerBound
+                    } else {
+                        break score;
+                    };
+
+                    if self.is_main {
+                        self.report(depth, pv_index + 1, score, bound, &self.best_line(state, depth));
+                    }
+                    delta *= 2;
+                };
+
+                let line = self.best_line(state, depth);
+                // There are fewer legal moves than lines asked for
+                if line.is_empty() && pv_index > 0 {
+                    break;
+                }
+
+                self.root_excluded.borrow_mut().extend(line.first());
+                lines.push((score, line));
+            }
+
+            // Lines searched later can still turn out better than earlier ones
+            lines.sort_by_key(|(score, _)| Reverse(*score));
+            best_res = lines;
+
+            if self.is_main {
+                for (pv_index, (score, line)) in best_res.iter().enumerate() {
+                    self.report(depth, pv_index + 1, *score, TranspositionEntryType::Exact, line);
+                }
+
+                let (score, line) = &best_res[0];
+                self.can_abort = !line.is_empty();
+
+                // Once there is a line for a mate within the asked amount of moves there is nothing left to search for
+                if let Some(mate_plies) = self.mate_plies
+                    && self.can_abort
+                    && *score >= CHECKMATE_EVAL - mate_plies as i32
+                {
+                    break;
+                }
+
+                if !self.should_start_iteration(iteration_start.elapsed()) {
+                    break;
+                }
+            }
+
+            depth += 1;
+        }
+
+        // Also report the nodes of the iteration that was stopped, so node limited searches show the exact count
+        if self.is_main && self.signals.stop.load(Ordering::Relaxed) {
+            println!("info {}", self.fmt_nodes());
+        }
+
+        best_res
+    }
+
+    /// Prints a line of an iteration, the bound tells whether the score is only a bound because the search fell outside of its window
+    fn report(&self, depth: u32, multi_pv: usize, score: i32, bound: TranspositionEntryType, line: &[Move]) {
+        let bound = match bound {
+            TranspositionEntryType::LowerBound => " lowerbound",
+            TranspositionEntryType::UpperBound => " upperbound",
+            TranspositionEntryType::Exact => "",
+        };
+        let pv = if line.is_empty() { String::new() } else { format!(" pv {}", fmt_moves(line)) };
+        println!(
+            "info depth {} seldepth {} multipv {} score {}{} {} hashfull {}{}",
+            depth,
+            self.seldepth.get(),
+            multi_pv,
+            fmt_score(score),
+            bound,
+            self.fmt_nodes(),
+            self.transposition_table.hashfull(),
+            pv
+        );
+    }
+
+    /// The nodes, nodes per second and time part of an info line
+    fn fmt_nodes(&self) -> String {
+        let nodes = self.nodes.load(Ordering::Relaxed);
+        let time = self.time_manager.elapsed();
+        format!(
+            "nodes {} nps {} time {}",
+            nodes,
+            (nodes as f64 / time.as_secs_f64().max(0.001)) as u64,
+            time.as_millis()
+        )
+    }
+
+    /// Returns the principal variation of the last iteration
+    ///
+    /// If the pv was cut short, e.g. by a transposition table cutoff, it is extended with the best moves from the table.
+    fn best_line(&self, state: &ChessState, depth: u32) -> Vec<Move> {
+        let mut moves = self.pv_table.borrow().first().cloned().unwrap_or_default();
+        // The root entry in the table doesn't know about the excluded root moves
+        if moves.is_empty() && !self.root_excluded.borrow().is_empty() {
+            return moves;
+        }
 
         let mut state = state.clone();
+        for m in &moves {
+            state.make_move(m);
+        }
+
+        while (moves.len() as u32) < depth && let Some(t) = self.transposition_table.get(state.hash) && let Some(m) = t.best_move {
+            // The table only stores the squares of the move, so look up the full legal move
+            let m = match state.gen_moves().into_iter().find(|mv| *mv == m) {
+                Some(m) => m,
+                None => break,
+            };
 
-        while depth > 0 && let Some(t) = TranspositionTable::get(state.hash) && let Some(m) = t.best_move {
             moves.push(m);
             state.make_move(&m);
-
-            depth-=1;
         }
 
         moves
     }
 }
 
+/// Mate scores are relative to the root, but the transposition table needs them relative to the node
+/// because the same position can be reached at different plies
+fn score_to_tt(score: i32, ply: u32) -> i32 {
+    if score >= MATE_THRESHOLD {
+        score + ply as i32
+    } else if score <= -MATE_THRESHOLD {
+        score - ply as i32
+    } else {
+        score
+    }
+}
+
+/// Converts a mate score from the transposition table back to being relative to the root
+fn score_from_tt(score: i32, ply: u32) -> i32 {
+    if score >= MATE_THRESHOLD {
+        score - ply as i32
+    } else if score <= -MATE_THRESHOLD {
+        score + ply as i32
+    } else {
+        score
+    }
+}
+
+/// Runs `main` on the calling thread while `threads - 1` helpers run on a copy of the position each,
+/// once `main` is done the helpers are told to stop through `helper_signals`
+fn with_helpers<R>(
+    threads: usize,
+    state: &mut ChessState,
+    helper_signals: &SearchSignals,
+    helper: impl Fn(usize, &mut ChessState) + Sync,
+    main: impl FnOnce(&mut ChessState) -> R,
+) -> R {
+    let pool = ThreadPoolBuilder::new().num_threads(threads - 1).build().unwrap();
+    // A normal scope would run on one of the pool's threads, so the main search would take the place of a helper
+    pool.in_place_scope(|s| {
+        for i in 1..threads {
+            let mut state = state.clone();
+            let helper = &helper;
+            s.spawn(move |_| helper(i, &mut state));
+        }
+
+        let res = main(state);
+        helper_signals.stop.store(true, Ordering::Relaxed);
+        res
+    })
+}
+
 fn fmt_moves(moves: &[Move]) -> String {
-    let mut s = String::new();
-    for m in moves {
-        s.push_str(&format!("{} ", m));
+    moves.iter().map(|m| m.to_string()).collect::<Vec<_>>().join(" ")
+}
+
+/// Formats a score as a UCI score, mates are given in moves instead of plies
+fn fmt_score(score: i32) -> String {
+    if score >= MATE_THRESHOLD {
+        format!("mate {}", (CHECKMATE_EVAL - score + 1) / 2)
+    } else if score <= -MATE_THRESHOLD {
+        // Negated as a number, so a mated root is mate 0 instead of mate -0
+        format!("mate {}", -(CHECKMATE_EVAL + score) / 2)
+    } else {
+        format!("cp {}", score)
     }
-    s
 }
 
 impl ChessState {
-    pub fn eval(&mut self, max_depth: Option<u32>, max_duration: Option<Duration>) -> (i32, Vec<Move>) {
-        let max_depth = max_depth.unwrap_or(MAX_DEPTH);
-        let max_duration = max_duration.unwrap_or(MAX_SEARCH_DURATION);
+    /// Searches the current position until one of the limits is reached or the search is stopped through `signals`
+    ///
+    /// A depth limited search with one thread and without variety always returns the same result for the same position.
+    pub fn eval(
+        &mut self,
+        limits: &SearchLimits,
+        options: &EngineOptions,
+        transposition_table: &TranspositionTable,
+        signals: &SearchSignals,
+    ) -> (i32, Vec<Move>) {
+        self.eval_multi_pv(limits, options, transposition_table, signals).swap_remove(0)
+    }
 
-        let mut best_res = (self.static_eval(), Vec::new());
+    /// Like `eval`, but returns the best `options.multi_pv` lines with their exact scores, best first
+    ///
+    /// There are fewer lines if the position has fewer legal moves, but always at least one.
+    pub fn eval_multi_pv(
+        &mut self,
+        limits: &SearchLimits,
+        options: &EngineOptions,
+        transposition_table: &TranspositionTable,
+        signals: &SearchSignals,
+    ) -> Vec<(i32, Vec<Move>)> {
+        let mut max_depth = limits.depth.unwrap_or(MAX_DEPTH);
+        // A mate in n moves is found after at most 2n - 1 plies
+        let mate_plies = limits.mate.map(|mate| (2 * mate).saturating_sub(1));
+        if let Some(mate_plies) = mate_plies {
+            max_depth = max_depth.min(mate_plies);
+        }
 
         if max_depth == 0 {
-            return best_res;
+            return vec![(self.static_eval(), Vec::new())];
         }
 
-        let search = Search {
-            search_end_time: Instant::now() + max_duration,
-        };
+        // Mate scores of normal searches may be wrong because of pruning, a proof must not build on them
+        if mate_plies.is_some() {
+            transposition_table.clear();
+        }
+        transposition_table.new_search();
 
-        let mut depth = 0;
-        let alpha = -CHECKMATE_EVAL;
-        let beta = CHECKMATE_EVAL;
-        while depth <= max_depth {
-            let res = search.search::<{ NodeType::Root }>(self, alpha, beta, depth as i32, 0);
+        let nodes = AtomicU64::new(0);
+        let time_manager = TimeManager::new(limits, self.turn, options.move_overhead);
+        let mut search = Search::new(time_manager, signals, transposition_table, options, &nodes);
+        search.mate_plies = mate_plies;
+        search.node_limit = limits.nodes;
 
-            if Instant::now() >= search.search_end_time {
-                break;
-            }
+        if options.threads <= 1 {
+            return search.iterative_deepening(self, 0, max_depth);
+        }
 
-            let line = search.best_line(self, depth);
-            println!("{} {} {}", depth, res, fmt_moves(&line));
+        // Lazy SMP: helper threads search the same position and only share their results through the transposition table
+        let helper_signals = SearchSignals::default();
+        with_helpers(
+            options.threads,
+            self,
+            &helper_signals,
+            |i, state| {
+                let helper_limits = SearchLimits {
+                    infinite: true,
+                    ..Default::default()
+                };
+                let time_manager = TimeManager::new(&helper_limits, state.turn, Duration::ZERO);
+                let mut helper = Search::new(time_manager, &helper_signals, transposition_table, options, &nodes);
+                helper.is_main = false;
+                helper.can_abort = true;
+                // Helpers only fill the table, so they don't need the further lines
+                helper.multi_pv = 1;
+                helper.mate_plies = mate_plies;
+                helper.node_limit = limits.nodes;
 
-            best_res = (res, line);
-            depth += 1;
-        }
+                // Start at staggered depths so the threads don't all search the same tree at the same time
+                helper.iterative_deepening(state, 1 + i as u32 % 2, max_depth);
+            },
+            |state| search.iterative_deepening(state, 0, max_depth),
+        )
+    }
 
-        best_res
+    /// Searches for a forced mate in at most `moves` moves of the side to move and returns the mating line if there is one
+    ///
+    /// The search stops as soon as a mate is found or once all lines up to that length are searched,
+    /// a `None` then proves that there is no such mate, unless the search was stopped through `signals`.
+    pub fn solve_mate(&mut self, moves: u32, options: &EngineOptions, transposition_table: &TranspositionTable, signals: &SearchSignals) -> Option<Vec<Move>> {
+        let limits = SearchLimits {
+            mate: Some(moves),
+            ..Default::default()
+        };
+        let (score, line) = self.eval(&limits, options, transposition_table, signals);
+
+        if moves > 0 && score >= CHECKMATE_EVAL - (2 * moves - 1) as i32 {
+            Some(line)
+        } else {
+            None
+        }
     }
 
     pub fn find_book_move(&mut self) -> Option<Move> {
@@ -244,11 +879,21 @@ impl ChessState {
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::chess_engine::PieceColor;
 
     #[test]
-    fn draw_by_repetition_test() {
-        TranspositionTable::init();
+    fn fmt_score_test() {
+        assert_eq!(fmt_score(35), "cp 35");
+        assert_eq!(fmt_score(-120), "cp -120");
+        assert_eq!(fmt_score(CHECKMATE_EVAL - 1), "mate 1");
+        assert_eq!(fmt_score(CHECKMATE_EVAL - 3), "mate 2");
+        assert_eq!(fmt_score(-CHECKMATE_EVAL + 2), "mate -1");
+        assert_eq!(fmt_score(-CHECKMATE_EVAL + 4), "mate -2");
+        assert_eq!(fmt_score(-CHECKMATE_EVAL), "mate 0");
+    }
 
+    #[test]
+    fn draw_by_repetition_test() {
         let mut state = ChessState::from_fen("rnb1kbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 1").unwrap();
         assert_eq!(state.is_draw_by_repetition, false);
 
@@ -264,8 +909,374 @@ mod tests {
         let mv = state.get_move("h3g1").unwrap();
         state.make_move(&mv);
 
-        let (score, mv) = state.eval(Some(1), None);
+        let (score, mv) = state.eval(
+            &SearchLimits {
+                depth: Some(1),
+                ..Default::default()
+            },
+            &EngineOptions::default(),
+            &TranspositionTable::new(16),
+            &SearchSignals::default(),
+        );
         assert_eq!(mv[0], state.get_move("h6g8").unwrap());
         assert_eq!(score, 0);
     }
+
+    #[test]
+    fn draw_at_root_test() {
+        let limits = SearchLimits {
+            depth: Some(5),
+            ..Default::default()
+        };
+
+        // The root position already occurred in the game
+        let mut state = ChessState::from_fen("rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 1").unwrap();
+        for m in ["g1f3", "g8f6", "f3g1", "f6g8"] {
+            let m = state.get_move(m).unwrap();
+            state.make_move(&m);
+        }
+        assert!(state.is_draw_by_repetition);
+        let (_, line) = state.eval(&limits, &EngineOptions::default(), &TranspositionTable::new(16), &SearchSignals::default());
+        assert!(!line.is_empty());
+
+        // The halfmove clock already reached the 50 move rule
+        let mut state = ChessState::from_fen("4k3/8/8/8/8/8/4P3/4K3 w - - 60 80").unwrap();
+        let (_, line) = state.eval(&limits, &EngineOptions::default(), &TranspositionTable::new(16), &SearchSignals::default());
+        assert!(!line.is_empty());
+    }
+
+    #[test]
+    fn lazy_smp_test() {
+        let mut state = ChessState::from_fen("6k1/5ppp/8/8/8/8/5PPP/R5K1 w - - 0 1").unwrap();
+
+        let (score, mv) = state.eval(
+            &SearchLimits {
+                depth: Some(4),
+                ..Default::default()
+            },
+            &EngineOptions {
+                threads: 4,
+                ..Default::default()
+            },
+            &TranspositionTable::new(16),
+            &SearchSignals::default(),
+        );
+        assert_eq!(mv[0], state.get_move("a1a8").unwrap());
+        assert!(score >= MATE_THRESHOLD);
+    }
+
+    #[test]
+    fn helpers_run_during_main_search_test() {
+        let mut state = ChessState::from_fen("6k1/5ppp/8/8/8/8/5PPP/R5K1 w - - 0 1").unwrap();
+        let helper_signals = SearchSignals::default();
+        let helper_started = AtomicBool::new(false);
+
+        // With two threads the only helper has to run next to the main thread, not once it is done
+        let helper_ran = with_helpers(
+            2,
+            &mut state,
+            &helper_signals,
+            |_, _| {
+                helper_started.store(true, Ordering::Relaxed);
+                while !helper_signals.stop.load(Ordering::Relaxed) {
+                    std::thread::sleep(Duration::from_millis(1));
+                }
+            },
+            |_| {
+                let start = Instant::now();
+                while !helper_started.load(Ordering::Relaxed) && start.elapsed() < Duration::from_secs(5) {
+                    std::thread::sleep(Duration::from_millis(1));
+                }
+                helper_started.load(Ordering::Relaxed)
+            },
+        );
+        assert!(helper_ran);
+    }
+
+    #[test]
+    fn pv_test() {
+        let mut state = ChessState::from_fen("r1bqkbnr/pppp1ppp/2n5/4p3/4P3/5N2/PPPP1PPP/RNBQKB1R w KQkq - 2 3").unwrap();
+
+        for depth in 1..=5 {
+            let (_, line) = state.eval(
+                &SearchLimits {
+                    depth: Some(depth),
+                    ..Default::default()
+                },
+                &EngineOptions::default(),
+                &TranspositionTable::new(16),
+                &SearchSignals::default(),
+            );
+            // The search is deterministic now, so the line of this position can be expected at its full length
+            assert_eq!(line.len(), depth as usize);
+
+            // Every move of the pv has to be legal in the position it is played in
+            let mut state = state.clone();
+            for m in line {
+                let m = state.get_move(&m.to_string()).unwrap();
+                state.make_move(&m);
+            }
+        }
+    }
+
+    #[test]
+    fn tt_mate_score_test() {
+        for ply in [0, 1, 7] {
+            for score in [35, -35, CHECKMATE_EVAL - 9, -CHECKMATE_EVAL + 12] {
+                assert_eq!(score_from_tt(score_to_tt(score, ply), ply), score);
+            }
+        }
+
+        // A mate in 3 plies seen from ply 2 is a mate in 1 ply from the node itself
+        assert_eq!(score_to_tt(CHECKMATE_EVAL - 3, 2), CHECKMATE_EVAL - 1);
+        assert_eq!(score_from_tt(CHECKMATE_EVAL - 1, 4), CHECKMATE_EVAL - 5);
+    }
+
+    #[test]
+    fn mate_in_n_test() {
+        // (fen, score)
+        let positions = [
+            ("7k/8/8/8/8/8/R7/1R4K1 w - - 0 1", CHECKMATE_EVAL - 3),
+            ("7k/8/8/8/8/8/R7/R5K1 w - - 0 1", CHECKMATE_EVAL - 5),
+            ("7k/1R6/8/8/8/8/R7/6K1 b - - 1 1", -CHECKMATE_EVAL + 2),
+        ];
+
+        for (fen, expected_score) in positions {
+            let mut state = ChessState::from_fen(fen).unwrap();
+
+            // Search deeper than the mate so the mate scores come back from the transposition table at different plies
+            let (score, line) = state.eval(
+                &SearchLimits {
+                    depth: Some(7),
+                    ..Default::default()
+                },
+                &EngineOptions::default(),
+                &TranspositionTable::new(16),
+                &SearchSignals::default(),
+            );
+            assert_eq!(score, expected_score, "{}", fen);
+
+            // The pv has to end in the mate
+            assert_eq!(line.len() as i32, CHECKMATE_EVAL - score.abs(), "{}", fen);
+            for m in line {
+                state.make_move(&m);
+            }
+            assert!(state.gen_moves().is_empty() && state.check[state.turn], "{}", fen);
+        }
+    }
+
+    /// Everything a `Search` borrows, so tests can call into a search directly
+    struct SearchSetup {
+        options: EngineOptions,
+        transposition_table: TranspositionTable,
+        signals: SearchSignals,
+        nodes: AtomicU64,
+    }
+
+    impl SearchSetup {
+        fn new() -> Self {
+            Self {
+                options: EngineOptions::default(),
+                transposition_table: TranspositionTable::new(16),
+                signals: SearchSignals::default(),
+                nodes: AtomicU64::new(0),
+            }
+        }
+
+        /// A search without time limits
+        fn search(&self) -> Search<'_> {
+            let time_manager = TimeManager::new(&SearchLimits::default(), PieceColor::White, Duration::ZERO);
+            Search::new(time_manager, &self.signals, &self.transposition_table, &self.options, &self.nodes)
+        }
+    }
+
+    #[test]
+    fn quiesce_check_test() {
+        let setup = SearchSetup::new();
+        let search = setup.search();
+
+        // Black is mated, standing pat would have returned its extra material
+        let mut state = ChessState::from_fen("R5k1/5ppp/8/7q/8/8/8/6K1 b - - 0 1").unwrap();
+        let score = search.search::<{ NodeType::Quiesce }>(&mut state, -CHECKMATE_EVAL, CHECKMATE_EVAL, -1, 3);
+        assert_eq!(score, -CHECKMATE_EVAL + 3);
+
+        // None of the evasions are captures
+        let mut state = ChessState::from_fen("R5k1/6pp/8/8/2q5/8/8/6K1 b - - 0 1").unwrap();
+        let score = search.search::<{ NodeType::Quiesce }>(&mut state, -CHECKMATE_EVAL, CHECKMATE_EVAL, -1, 3);
+        assert!(score > 0);
+    }
+
+    #[test]
+    fn singular_verification_test() {
+        let setup = SearchSetup::new();
+        let search = setup.search();
+        let transposition_table = &setup.transposition_table;
+
+        // The verification search must not overwrite the entry it is verifying
+        let mut state = ChessState::from_fen("r1bqkbnr/pppp1ppp/2n5/4p3/4P3/5N2/PPPP1PPP/RNBQKB1R w KQkq - 2 3").unwrap();
+        let m = state.get_move("f1b5").unwrap();
+        let entry = TranspositionEntry {
+            key: state.hash,
+            entry_type: TranspositionEntryType::LowerBound,
+            depth: 10,
+            score: 30,
+            best_move: Some(m),
+        };
+        transposition_table.set(state.hash, entry);
+
+        search.excluded_move.set(Some((0, m)));
+        search.search::<{ NodeType::Cut }>(&mut state, -1, 0, 3, 0);
+        let stored = transposition_table.get(state.hash).unwrap();
+        assert_eq!((stored.depth, stored.score, stored.best_move), (10, 30, Some(m)));
+
+        // Without the only legal move there is no mate, the move is just singular
+        let mut state = ChessState::from_fen("k7/8/8/8/8/8/1q6/K7 w - - 0 1").unwrap();
+        let m = state.get_move("a1b2").unwrap();
+        search.excluded_move.set(Some((0, m)));
+        assert_eq!(search.search::<{ NodeType::Cut }>(&mut state, -1, 0, 3, 0), -1);
+    }
+
+    #[test]
+    fn node_limit_test() {
+        let setup = SearchSetup::new();
+        let mut search = setup.search();
+        search.node_limit = Some(5000);
+
+        let mut state = ChessState::from_fen("r1bqkbnr/pppp1ppp/2n5/4p3/4P3/5N2/PPPP1PPP/RNBQKB1R w KQkq - 2 3").unwrap();
+        let (_, line) = search.iterative_deepening(&mut state, 0, MAX_DEPTH).swap_remove(0);
+        assert!(!line.is_empty());
+        assert_eq!(setup.nodes.load(Ordering::Relaxed), 5000);
+
+        // Threads that share the counter together stay within the limit too
+        let setup = SearchSetup::new();
+        std::thread::scope(|s| {
+            for _ in 0..4 {
+                let (mut state, setup) = (state.clone(), &setup);
+                s.spawn(move || {
+                    let mut search = setup.search();
+                    search.can_abort = true;
+                    search.node_limit = Some(5000);
+                    search.iterative_deepening(&mut state, 1, MAX_DEPTH);
+                });
+            }
+        });
+        assert_eq!(setup.nodes.load(Ordering::Relaxed), 5000);
+    }
+
+    #[test]
+    fn deterministic_search_test() {
+        let fen = "r1bqkbnr/pppp1ppp/2n5/4p3/4P3/5N2/PPPP1PPP/RNBQKB1R w KQkq - 2 3";
+        let limits = SearchLimits {
+            depth: Some(6),
+            ..Default::default()
+        };
+        let search = |options: &EngineOptions| {
+            let mut state = ChessState::from_fen(fen).unwrap();
+            state.eval(&limits, options, &TranspositionTable::new(16), &SearchSignals::default())
+        };
+
+        let options = EngineOptions::default();
+        assert_eq!(search(&options), search(&options));
+
+        // With variety the result only changes with the seed
+        let options = EngineOptions {
+            variety: 50,
+            seed: 7,
+            ..Default::default()
+        };
+        assert_eq!(search(&options), search(&options));
+        assert!((1..10).any(|seed| search(&EngineOptions { seed, ..options.clone() }).0 != search(&options).0));
+    }
+
+    #[test]
+    fn solve_mate_test() {
+        // (fen, moves until mate)
+        let positions = [
+            ("7k/8/8/8/8/8/R7/R5K1 w - - 0 1", 3),
+            // The only mate starts with a quiet king move
+            ("k7/8/2K5/8/8/8/8/1R6 w - - 0 1", 2),
+        ];
+
+        for (fen, moves) in positions {
+            let mut state = ChessState::from_fen(fen).unwrap();
+            let transposition_table = TranspositionTable::new(16);
+            let options = EngineOptions::default();
+            let signals = SearchSignals::default();
+
+            assert!(state.solve_mate(moves - 1, &options, &transposition_table, &signals).is_none(), "{}", fen);
+
+            let line = state.solve_mate(moves, &options, &transposition_table, &signals).unwrap();
+            assert_eq!(line.len() as u32, 2 * moves - 1, "{}", fen);
+            for m in line {
+                state.make_move(&m);
+            }
+            assert!(state.gen_moves().is_empty() && state.check[state.turn], "{}", fen);
+        }
+    }
+
+    #[test]
+    fn mate_score_from_previous_search_test() {
+        let transposition_table = TranspositionTable::new(16);
+        let mut state = ChessState::from_fen("7k/8/8/8/8/8/R7/R5K1 w - - 0 1").unwrap();
+
+        let limits = SearchLimits {
+            depth: Some(7),
+            ..Default::default()
+        };
+        let (score, line) = state.eval(&limits, &EngineOptions::default(), &transposition_table, &SearchSignals::default());
+        assert_eq!(score, CHECKMATE_EVAL - 5);
+
+        // After two moves the table entries of the previous search are two plies closer to the root
+        state.make_move(&line[0]);
+        state.make_move(&line[1]);
+
+        let limits = SearchLimits {
+            depth: Some(1),
+            ..Default::default()
+        };
+        let (score, _) = state.eval(&limits, &EngineOptions::default(), &transposition_table, &SearchSignals::default());
+        assert_eq!(score, CHECKMATE_EVAL - 3);
+    }
+
+    #[test]
+    fn multi_pv_test() {
+        let limits = SearchLimits {
+            depth: Some(5),
+            ..Default::default()
+        };
+        let options = EngineOptions {
+            multi_pv: 3,
+            ..Default::default()
+        };
+        let search = |fen: &str| {
+            let mut state = ChessState::from_fen(fen).unwrap();
+            state.eval_multi_pv(&limits, &options, &TranspositionTable::new(16), &SearchSignals::default())
+        };
+
+        // Only the best line mates
+        let lines = search("6k1/5ppp/8/8/8/8/8/R5K1 w - - 0 1");
+        assert_eq!(lines.len(), 3);
+        assert_eq!(lines[0].0, CHECKMATE_EVAL - 1);
+        assert_eq!(lines[0].1[0].to_string(), "a1a8");
+        assert!(lines[1].0 < MATE_THRESHOLD);
+
+        let fen = "r1bqkbnr/pppp1ppp/2n5/4p3/4P3/5N2/PPPP1PPP/RNBQKB1R w KQkq - 2 3";
+        let lines = search(fen);
+        assert_eq!(lines.len(), 3);
+        assert!(lines.windows(2).all(|w| w[0].0 >= w[1].0));
+        for (i, (_, line)) in lines.iter().enumerate() {
+            assert!(lines[..i].iter().all(|(_, other)| other[0] != line[0]));
+
+            let mut state = ChessState::from_fen(fen).unwrap();
+            for m in line {
+                assert!(state.gen_moves().contains(m), "{}", fmt_moves(line));
+                state.make_move(m);
+            }
+        }
+
+        // There is only one legal move
+        let lines = search("k7/8/8/8/8/8/1q6/K7 w - - 0 1");
+        assert_eq!(lines.len(), 1);
+        assert_eq!(lines[0].1[0].to_string(), "a1b2");
+    }
 }
@@ -0,0 +1,213 @@
+/// Tunable parameters of the search's pruning and reduction techniques
+#[derive(Clone, Debug)]
+pub struct SearchParams {
+    /// Late move reductions are only done with at least this much depth left
+    pub lmr_min_depth: i32,
+    /// The amount of moves that are searched without reduction in a node
+    pub lmr_full_depth_moves: u32,
+    /// Reduction = lmr_base + ln(depth) * ln(move number) / lmr_divisor
+    pub lmr_base: f64,
+    pub lmr_divisor: f64,
+
+    /// Late move pruning is only done with at most this much depth left
+    pub lmp_max_depth: i32,
+    /// Quiet moves after the first lmp_base + depth * depth moves are pruned
+    pub lmp_base: u32,
+
+    /// Reverse futility pruning returns if the static evaluation minus rfp_margin per depth is still above beta
+    pub rfp_max_depth: i32,
+    pub rfp_margin: i32,
+    /// Futility pruning skips quiet moves if the static evaluation plus futility_margin per depth is below alpha
+    pub futility_max_depth: i32,
+    pub futility_margin: i32,
+    /// Razoring drops into the quiesce search if the static evaluation plus razor_margin per depth is below alpha
+    pub razor_max_depth: i32,
+    pub razor_margin: i32,
+
+    /// Internal iterative deepening searches pv nodes without a transposition table move with iid_reduction less depth first,
+    /// cut nodes without one are reduced by one ply instead
+    pub iid_min_depth: i32,
+    pub iid_reduction: i32,
+
+    /// Singular extensions are only tried with at least this much depth left
+    pub singular_min_depth: i32,
+    /// The transposition table move is singular if all other moves stay singular_margin per depth below its score
+    pub singular_margin: i32,
+
+    /// Iterations from this depth on start with a window around the score of the previous iteration
+    pub aspiration_min_depth: u32,
+    /// The initial distance of the window bounds to the previous score, it doubles every time the search falls outside of it
+    pub aspiration_window: i32,
+}
+
+impl Default for SearchParams {
+    fn default() -> Self {
+        Self {
+            lmr_min_depth: 3,
+            lmr_full_depth_moves: 3,
+            lmr_base: 0.75,
+            lmr_divisor: 2.25,
+
+            lmp_max_depth: 3,
+            lmp_base: 3,
+
+            rfp_max_depth: 6,
+            rfp_margin: 90,
+            futility_max_depth: 3,
+            futility_margin: 120,
+            razor_max_depth: 2,
+            razor_margin: 250,
+
+            iid_min_depth: 5,
+            iid_reduction: 2,
+
+            singular_min_depth: 8,
+            singular_margin: 2,
+
+            aspiration_min_depth: 5,
+            aspiration_window: 25,
+        }
+    }
+}
+
+/// Depths and move numbers above this share the reduction of the last table entry
+pub const REDUCTION_TABLE_SIZE: usize = 64;
+
+impl SearchParams {
+    /// The parameters as UCI spin options with their minimum and maximum, lmr_base and lmr_divisor are given in hundredths
+    pub const UCI_OPTIONS: [(&'static str, i32, i32); 18] = [
+        ("LmrMinDepth", 1, 20),
+        ("LmrFullDepthMoves", 0, 64),
+        ("LmrBase", 0, 300),
+        ("LmrDivisor", 50, 1000),
+        ("LmpMaxDepth", 0, 20),
+        ("LmpBase", 0, 64),
+        ("RfpMaxDepth", 0, 20),
+        ("RfpMargin", 0, 1000),
+        ("FutilityMaxDepth", 0, 20),
+        ("FutilityMargin", 0, 1000),
+        ("RazorMaxDepth", 0, 20),
+        ("RazorMargin", 0, 2000),
+        ("IidMinDepth", 1, 64),
+        // Without any reduction the internal search would never end
+        ("IidReduction", 1, 20),
+        ("SingularMinDepth", 1, 64),
+        ("SingularMargin", 0, 100),
+        ("AspirationMinDepth", 1, 64),
+        ("AspirationWindow", 1, 1000),
+    ];
+
+    /// The value of a parameter as shown in its UCI option
+    pub fn uci_value(&self, name: &str) -> Option<i32> {
+        Some(match name {
+            "LmrMinDepth" => self.lmr_min_depth,
+            "LmrFullDepthMoves" => self.lmr_full_depth_moves as i32,
+            "LmrBase" => (self.lmr_base * 100.0).round() as i32,
+            "LmrDivisor" => (self.lmr_divisor * 100.0).round() as i32,
+            "LmpMaxDepth" => self.lmp_max_depth,
+            "LmpBase" => self.lmp_base as i32,
+            "RfpMaxDepth" => self.rfp_max_depth,
+            "RfpMargin" => self.rfp_margin,
+            "FutilityMaxDepth" => self.futility_max_depth,
+            "FutilityMargin" => self.futility_margin,
+            "RazorMaxDepth" => self.razor_max_depth,
+            "RazorMargin" => self.razor_margin,
+            "IidMinDepth" => self.iid_min_depth,
+            "IidReduction" => self.iid_reduction,
+            "SingularMinDepth" => self.singular_min_depth,
+            "SingularMargin" => self.singular_margin,
+            "AspirationMinDepth" => self.aspiration_min_depth as i32,
+            "AspirationWindow" => self.aspiration_window,
+            _ => return None,
+        })
+    }
+
+    /// Sets a parameter through its UCI option, the value has to be within the range of the option
+    ///
+    /// Returns false if there is no parameter with that name.
+    pub fn set_uci_value(&mut self, name: &str, value: i32) -> bool {
+        match name {
+            "LmrMinDepth" => self.lmr_min_depth = value,
+            "LmrFullDepthMoves" => self.lmr_full_depth_moves = value as u32,
+            "LmrBase" => self.lmr_base = value as f64 / 100.0,
+            "LmrDivisor" => self.lmr_divisor = value as f64 / 100.0,
+            "LmpMaxDepth" => self.lmp_max_depth = value,
+            "LmpBase" => self.lmp_base = value as u32,
+            "RfpMaxDepth" => self.rfp_max_depth = value,
+            "RfpMargin" => self.rfp_margin = value,
+            "FutilityMaxDepth" => self.futility_max_depth = value,
+            "FutilityMargin" => self.futility_margin = value,
+            "RazorMaxDepth" => self.razor_max_depth = value,
+            "RazorMargin" => self.razor_margin = value,
+            "IidMinDepth" => self.iid_min_depth = value,
+            "IidReduction" => self.iid_reduction = value,
+            "SingularMinDepth" => self.singular_min_depth = value,
+            "SingularMargin" => self.singular_margin = value,
+            "AspirationMinDepth" => self.aspiration_min_depth = value as u32,
+            "AspirationWindow" => self.aspiration_window = value,
+            _ => return false,
+        }
+
+        true
+    }
+
+    /// Precalculates the late move reductions indexed by depth and move number
+    pub fn reduction_table(&self) -> [[i32; REDUCTION_TABLE_SIZE]; REDUCTION_TABLE_SIZE] {
+        let mut table = [[0; REDUCTION_TABLE_SIZE]; REDUCTION_TABLE_SIZE];
+
+        for (depth, row) in table.iter_mut().enumerate().skip(1) {
+            for (move_number, reduction) in row.iter_mut().enumerate().skip(1) {
+                *reduction = (self.lmr_base + (depth as f64).ln() * (move_number as f64).ln() / self.lmr_divisor) as i32;
+            }
+        }
+
+        table
+    }
+
+    /// The amount of quiet moves that are searched before the rest is pruned
+    pub fn lmp_move_count(&self, depth: i32) -> u32 {
+        self.lmp_base + (depth * depth) as u32
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn uci_options_test() {
+        let defaults = SearchParams::default();
+        let mut params = SearchParams::default();
+
+        for (name, min, max) in SearchParams::UCI_OPTIONS {
+            // The defaults are within the range of their option and survive being set through it
+            let value = defaults.uci_value(name).unwrap();
+            assert!(min <= value && value <= max, "{}", name);
+            assert!(params.set_uci_value(name, value));
+            assert_eq!(params.uci_value(name), Some(value));
+
+            assert!(params.set_uci_value(name, max));
+            assert_eq!(params.uci_value(name), Some(max), "{}", name);
+        }
+
+        assert_eq!(defaults.uci_value("Foo"), None);
+        assert!(!params.set_uci_value("Foo", 1));
+    }
+
+    #[test]
+    fn reduction_table_test() {
+        let table = SearchParams::default().reduction_table();
+
+        assert_eq!(table[1][1], 0);
+        assert_eq!(table[3][4], 1);
+        assert_eq!(table[10][30], 4);
+
+        // Reductions never shrink with more depth or later moves
+        for depth in 1..REDUCTION_TABLE_SIZE {
+            for move_number in 1..REDUCTION_TABLE_SIZE {
+                assert!(table[depth][move_number] >= table[depth - 1][move_number]);
+                assert!(table[depth][move_number] >= table[depth][move_number - 1]);
+            }
+        }
+    }
+}
@@ -0,0 +1,157 @@
+use super::{gen_moves::Move, with_offset, ChessState, Piece, PieceColor, PieceType};
+
+/// Piece values for the exchange evaluation, the king can never be traded off
+const fn see_value(t: PieceType) -> i32 {
+    match t {
+        PieceType::Pawn => 100,
+        PieceType::Knight => 300,
+        PieceType::Bishop => 300,
+        PieceType::Rook => 500,
+        PieceType::Queen => 900,
+        PieceType::King => 20000,
+    }
+}
+
+/// Finds the square of the least valuable piece of `attacker` that attacks `square`
+///
+/// Works like `ChessState::is_square_attacked_by` but on a board from which pieces have already been traded off,
+/// so sliding pieces behind a piece that captured are found once it is gone.
+fn least_valuable_attacker(pieces: &[Option<Piece>; 64], square: u8, attacker: PieceColor) -> Option<u8> {
+    let mut best: Option<(i32, u8)> = None;
+    let mut consider = |from: u8, t: PieceType| {
+        if best.is_none_or(|(value, _)| see_value(t) < value) {
+            best = Some((see_value(t), from));
+        }
+    };
+
+    let backward = match attacker {
+        PieceColor::White => -10,
+        PieceColor::Black => 10,
+    };
+    for offset in [1_i8, -1] {
+        if let Some(from) = with_offset(square, backward + offset)
+            && pieces[from as usize]
+                == Some(Piece {
+                    c: attacker,
+                    t: PieceType::Pawn,
+                })
+        {
+            consider(from, PieceType::Pawn);
+        }
+    }
+
+    for offset in PieceType::Knight.offsets() {
+        if let Some(from) = with_offset(square, *offset)
+            && pieces[from as usize]
+                == Some(Piece {
+                    c: attacker,
+                    t: PieceType::Knight,
+                })
+        {
+            consider(from, PieceType::Knight);
+        }
+    }
+
+    for slider in [PieceType::Rook, PieceType::Bishop] {
+        for offset in slider.offsets() {
+            let mut slid = false;
+            let mut from = square;
+            while let Some(n) = with_offset(from, *offset) {
+                from = n;
+
+                if let Some(piece) = pieces[from as usize] {
+                    if piece.c == attacker && (piece.t == slider || piece.t == PieceType::Queen || (piece.t == PieceType::King && !slid)) {
+                        consider(from, piece.t);
+                    }
+                    break;
+                }
+
+                slid = true;
+            }
+        }
+    }
+
+    best.map(|(_, from)| from)
+}
+
+impl ChessState {
+    /// Static exchange evaluation: the material won by a move if both sides keep recapturing on its target square
+    /// with their least valuable piece, each side may stop capturing once that is better for it
+    pub fn see(&self, m: &Move) -> i32 {
+        let mut pieces = self.pieces;
+        let mut gains = [0; 32];
+
+        let mut on_square = m.promote_to.unwrap_or(m.piece_type);
+        gains[0] = m.capture.map_or(0, see_value) + see_value(on_square) - see_value(m.piece_type);
+
+        pieces[m.from as usize] = None;
+        pieces[m.to as usize] = Some(Piece { c: self.turn, t: on_square });
+        if m.en_passant {
+            let captured = match self.turn {
+                PieceColor::White => m.to - 8,
+                PieceColor::Black => m.to + 8,
+            };
+            pieces[captured as usize] = None;
+        }
+
+        let mut side = self.turn.opposite();
+        let mut depth = 0;
+        while depth + 1 < gains.len() {
+            let from = match least_valuable_attacker(&pieces, m.to, side) {
+                Some(from) => from,
+                None => break,
+            };
+
+            // The gain of this side if it captures and the other side stops afterwards
+            depth += 1;
+            gains[depth] = see_value(on_square) - gains[depth - 1];
+
+            on_square = pieces[from as usize].unwrap().t;
+            pieces[from as usize] = None;
+            pieces[m.to as usize] = Some(Piece { c: side, t: on_square });
+            side = side.opposite();
+        }
+
+        // Going backwards each side chooses between capturing and stopping
+        while depth > 0 {
+            gains[depth - 1] = -i32::max(-gains[depth - 1], gains[depth]);
+            depth -= 1;
+        }
+
+        gains[0]
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::chess_engine::ChessState;
+
+    #[test]
+    fn see_test() {
+        // (fen, move, expected gain)
+        let positions = [
+            // Undefended pawn
+            ("4k3/8/8/3p4/8/8/8/3QK3 w - - 0 1", "d1d5", 100),
+            // Pawn defended by a pawn
+            ("4k3/8/4p3/3p4/8/8/8/3QK3 w - - 0 1", "d1d5", -800),
+            // Knight takes a defended knight
+            ("4k3/4r3/8/4n3/8/3N4/8/4K3 w - - 0 1", "d3e5", 0),
+            // The queen behind the rook joins the exchange
+            ("3rk3/8/8/3p4/8/8/3R4/3QK3 w - - 0 1", "d2d5", 100),
+            // Without the queen behind it the rook is lost
+            ("3rk3/8/8/3p4/8/8/3R4/4K3 w - - 0 1", "d2d5", -400),
+            // The king can't recapture on a defended square
+            ("8/8/3qk3/8/8/3R4/8/3RK3 w - - 0 1", "d3d6", 900),
+            // En passant
+            ("4k3/8/8/3pP3/8/8/8/4K3 w - d6 0 1", "e5d6", 100),
+            // Promoting with a capture, the king takes back the queen
+            ("3rk3/4P3/8/8/8/8/8/4K3 w - - 0 1", "e7d8q", 400),
+        ];
+
+        for (fen, m, expected) in positions {
+            let mut state = ChessState::from_fen(fen).unwrap();
+            let m = state.get_move(m).unwrap();
+            assert_eq!(state.see(&m), expected, "{} {}", fen, m);
+        }
+    }
+}
@@ -1,5 +1,3 @@
-use rand::Rng;
-
 use super::{gen_moves::Move, ChessState, Piece, PieceColor, PieceColorArray, PieceType};
 
 #[rustfmt::skip]
@@ -238,11 +236,19 @@ impl ChessState {
         let is_endgame = 24 - is_middlegame;
         (middlegame_score * is_middlegame + endgame_score * is_endgame) / 24
     }
+
+    /// Checks whether the color has any pieces left besides pawns and its king
+    pub fn has_non_pawn_material(&self, color: PieceColor) -> bool {
+        self.pieces
+            .iter()
+            .flatten()
+            .any(|p| p.c == color && p.t != PieceType::Pawn && p.t != PieceType::King)
+    }
 }
 
 impl Move {
     pub fn static_eval(&self) -> i32 {
-        let mut v = rand::thread_rng().gen_range(-10..=10);
+        let mut v = 0;
 
         if let Some(t) = self.capture {
             v += LVA_MVV[self.piece_type as usize][t as usize];
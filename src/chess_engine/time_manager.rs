@@ -0,0 +1,172 @@
+use std::time::{Duration, Instant};
+
+use super::PieceColor;
+
+/// Amount of moves we expect to still play if the GUI does not send movestogo
+const DEFAULT_MOVES_TO_GO: u32 = 30;
+/// Rough factor by which an iteration of the iterative deepening takes longer than the previous one
+const ITERATION_GROWTH: u32 = 2;
+
+/// The limits of a single search as given by the UCI go command
+#[derive(Clone, Default, Debug)]
+pub struct SearchLimits {
+    pub depth: Option<u32>,
+    /// The search never looks at more nodes than this, only the first iteration may exceed it so there is a move to play
+    pub nodes: Option<u64>,
+    pub mate: Option<u32>,
+    pub movetime: Option<Duration>,
+    pub infinite: bool,
+    /// Search on the opponent's time, the other limits only apply after a ponderhit
+    pub ponder: bool,
+
+    pub wtime: Option<Duration>,
+    pub btime: Option<Duration>,
+    pub winc: Duration,
+    pub binc: Duration,
+    pub movestogo: Option<u32>,
+}
+
+/// Decides how long a search may take
+///
+/// The soft limit is the time after which no new iteration of the iterative deepening is started,
+/// the hard limit is the time after which a running search is aborted.
+pub struct TimeManager {
+    start: Instant,
+    soft_limit: Option<Duration>,
+    hard_limit: Option<Duration>,
+}
+
+impl TimeManager {
+    /// `move_overhead` is the time kept in reserve for the communication with the GUI
+    pub fn new(limits: &SearchLimits, turn: PieceColor, move_overhead: Duration) -> Self {
+        let (soft_limit, hard_limit) = if limits.infinite {
+            (None, None)
+        } else if let Some(movetime) = limits.movetime {
+            let time = movetime.saturating_sub(move_overhead);
+            (Some(time), Some(time))
+        } else {
+            let (time, inc) = match turn {
+                PieceColor::White => (limits.wtime, limits.winc),
+                PieceColor::Black => (limits.btime, limits.binc),
+            };
+
+            match time {
+                Some(time) => {
+                    let available = time.saturating_sub(move_overhead);
+                    let moves_to_go = limits.movestogo.unwrap_or(DEFAULT_MOVES_TO_GO).max(1);
+
+                    // Spread the remaining time evenly over the remaining moves but never plan to use more than half of it
+                    let soft = (available / moves_to_go + inc * 3 / 4).min(available / 2);
+                    // Allow a single move to take longer if an iteration is already running
+                    let hard = (soft * 4).min(available * 4 / 5);
+                    (Some(soft), Some(hard))
+                }
+                None => (None, None),
+            }
+        };
+
+        Self {
+            start: Instant::now(),
+            soft_limit,
+            hard_limit,
+        }
+    }
+
+    pub fn elapsed(&self) -> Duration {
+        self.start.elapsed()
+    }
+
+    pub fn hard_limit_reached(&self) -> bool {
+        self.hard_limit.is_some_and(|hard_limit| self.elapsed() >= hard_limit)
+    }
+
+    /// Checks whether there is enough time left for another iteration, given how long the last one took
+    pub fn should_start_iteration(&self, last_iteration: Duration) -> bool {
+        let elapsed = self.elapsed();
+
+        if self.soft_limit.is_some_and(|soft_limit| elapsed >= soft_limit) {
+            return false;
+        }
+
+        // Don't start an iteration that would most likely be aborted by the hard limit anyway
+        self.hard_limit
+            .is_none_or(|hard_limit| elapsed + last_iteration * ITERATION_GROWTH < hard_limit)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const MOVE_OVERHEAD: Duration = Duration::from_millis(30);
+
+    #[test]
+    fn infinite_test() {
+        let time_manager = TimeManager::new(
+            &SearchLimits {
+                infinite: true,
+                wtime: Some(Duration::from_millis(10)),
+                ..Default::default()
+            },
+            PieceColor::White,
+            MOVE_OVERHEAD,
+        );
+
+        assert_eq!(time_manager.soft_limit, None);
+        assert_eq!(time_manager.hard_limit, None);
+        assert!(time_manager.should_start_iteration(Duration::from_secs(1000)));
+    }
+
+    #[test]
+    fn movetime_test() {
+        let time_manager = TimeManager::new(
+            &SearchLimits {
+                movetime: Some(Duration::from_millis(1000)),
+                ..Default::default()
+            },
+            PieceColor::Black,
+            MOVE_OVERHEAD,
+        );
+
+        assert_eq!(time_manager.soft_limit, Some(Duration::from_millis(970)));
+        assert_eq!(time_manager.hard_limit, Some(Duration::from_millis(970)));
+        assert!(time_manager.should_start_iteration(Duration::from_millis(10)));
+        assert!(!time_manager.should_start_iteration(Duration::from_millis(600)));
+    }
+
+    #[test]
+    fn clock_test() {
+        let limits = SearchLimits {
+            wtime: Some(Duration::from_millis(60_030)),
+            btime: Some(Duration::from_millis(1_030)),
+            winc: Duration::from_millis(1000),
+            binc: Duration::from_millis(1000),
+            ..Default::default()
+        };
+
+        let time_manager = TimeManager::new(&limits, PieceColor::White, MOVE_OVERHEAD);
+        assert_eq!(time_manager.soft_limit, Some(Duration::from_millis(2750)));
+        assert_eq!(time_manager.hard_limit, Some(Duration::from_millis(11000)));
+
+        // Low on time the increment must not make us use up the whole clock
+        let time_manager = TimeManager::new(&limits, PieceColor::Black, MOVE_OVERHEAD);
+        assert_eq!(time_manager.soft_limit, Some(Duration::from_millis(500)));
+        assert_eq!(time_manager.hard_limit, Some(Duration::from_millis(800)));
+    }
+
+    #[test]
+    fn movestogo_test() {
+        let time_manager = TimeManager::new(
+            &SearchLimits {
+                wtime: Some(Duration::from_millis(10_030)),
+                movestogo: Some(1),
+                ..Default::default()
+            },
+            PieceColor::White,
+            MOVE_OVERHEAD,
+        );
+
+        assert_eq!(time_manager.soft_limit, Some(Duration::from_millis(5000)));
+        assert_eq!(time_manager.hard_limit, Some(Duration::from_millis(8000)));
+    }
+}
@@ -1,14 +1,17 @@
-use std::sync::RwLock;
+use std::{
+    mem::size_of,
+    sync::atomic::{AtomicU64, AtomicU8, Ordering},
+};
 
-use super::gen_moves::Move;
-
-// const TRANSPOSITION_ENTRIES: usize = 100_000_000;
-const TRANSPOSITION_ENTRIES: usize = 200_000_000;
-
-static mut TRANSPOSITION_TABLE: Option<RwLock<TranspositionTable>> = None;
+use super::{gen_moves::Move, PieceType};
 
+/// A transposition table that can be shared by multiple search threads without locking
 pub struct TranspositionTable {
-    entries: Vec<Option<TranspositionEntry>>,
+    entries: Vec<AtomicEntry>,
+    /// The amount of entries is always a power of two so the index can be calculated by masking the key
+    mask: usize,
+    /// Incremented for every new search so entries of old searches get replaced first
+    age: AtomicU8,
 }
 
 #[derive(Clone, Copy)]
@@ -17,6 +20,7 @@ pub struct TranspositionEntry {
     pub entry_type: TranspositionEntryType,
     pub depth: i32,
     pub score: i32,
+    /// Only from, to and promote_to are stored, so this has to be matched against a generated move before making it
     pub best_move: Option<Move>,
 }
 
@@ -27,27 +31,257 @@ pub enum TranspositionEntryType {
     Exact,
 }
 
+/// A transposition entry packed into 16 bytes
+///
+/// Layout of data from the lowest bit:
+/// 16 bits move, 2 bits entry type (0 means empty), 8 bits depth, 6 bits age, 32 bits score
+#[derive(Clone, Copy, Default)]
+struct PackedEntry {
+    key: u64,
+    data: u64,
+}
+
+const AGE_BITS: u8 = 6;
+
+/// A packed entry stored as two atomics
+///
+/// The key is stored xored with the data, so an entry torn by two threads writing at the same time
+/// does not match its key anymore and is treated as a miss instead of returning garbage.
+#[derive(Default)]
+struct AtomicEntry {
+    key: AtomicU64,
+    data: AtomicU64,
+}
+
+impl AtomicEntry {
+    fn load(&self) -> PackedEntry {
+        let data = self.data.load(Ordering::Relaxed);
+        PackedEntry {
+            key: self.key.load(Ordering::Relaxed) ^ data,
+            data,
+        }
+    }
+
+    fn store(&self, entry: PackedEntry) {
+        self.key.store(entry.key ^ entry.data, Ordering::Relaxed);
+        self.data.store(entry.data, Ordering::Relaxed);
+    }
+}
+
+impl PackedEntry {
+    fn pack(entry: &TranspositionEntry, age: u8) -> Self {
+        let entry_type = match entry.entry_type {
+            TranspositionEntryType::LowerBound => 1,
+            TranspositionEntryType::UpperBound => 2,
+            TranspositionEntryType::Exact => 3,
+        };
+        let depth = entry.depth.clamp(i8::MIN as i32, i8::MAX as i32) as i8 as u8;
+
+        Self {
+            key: entry.key,
+            data: pack_move(entry.best_move) as u64
+                | entry_type << 16
+                | (depth as u64) << 18
+                | ((age & ((1 << AGE_BITS) - 1)) as u64) << 26
+                | (entry.score as u32 as u64) << 32,
+        }
+    }
+
+    fn unpack(&self) -> Option<TranspositionEntry> {
+        let entry_type = match (self.data >> 16) & 3 {
+            0 => return None,
+            1 => TranspositionEntryType::LowerBound,
+            2 => TranspositionEntryType::UpperBound,
+            _ => TranspositionEntryType::Exact,
+        };
+
+        Some(TranspositionEntry {
+            key: self.key,
+            entry_type,
+            depth: self.depth(),
+            score: (self.data >> 32) as u32 as i32,
+            best_move: unpack_move(self.data as u16),
+        })
+    }
+
+    fn is_empty(&self) -> bool {
+        (self.data >> 16) & 3 == 0
+    }
+
+    fn depth(&self) -> i32 {
+        (self.data >> 18) as u8 as i8 as i32
+    }
+
+    fn age(&self) -> u8 {
+        ((self.data >> 26) & ((1 << AGE_BITS) - 1)) as u8
+    }
+}
+
+/// Packs a move into 16 bits: 6 bits from, 6 bits to, 3 bits promotion, 0 means no move
+fn pack_move(m: Option<Move>) -> u16 {
+    match m {
+        Some(m) => {
+            let promote_to = match m.promote_to {
+                None => 0,
+                Some(PieceType::Knight) => 1,
+                Some(PieceType::Bishop) => 2,
+                Some(PieceType::Rook) => 3,
+                Some(_) => 4,
+            };
+            m.from as u16 | (m.to as u16) << 6 | promote_to << 12
+        }
+        None => 0,
+    }
+}
+
+fn unpack_move(m: u16) -> Option<Move> {
+    if m == 0 {
+        return None;
+    }
+
+    Some(Move {
+        from: (m & 63) as u8,
+        to: ((m >> 6) & 63) as u8,
+        promote_to: match m >> 12 {
+            1 => Some(PieceType::Knight),
+            2 => Some(PieceType::Bishop),
+            3 => Some(PieceType::Rook),
+            4 => Some(PieceType::Queen),
+            _ => None,
+        },
+        ..Default::default()
+    })
+}
+
+/// The largest power of two of entries that fits into `size_mb` megabytes
+fn entry_count(size_mb: usize) -> usize {
+    let max_entries = (size_mb * 1024 * 1024 / size_of::<PackedEntry>()).max(1);
+    1 << (usize::BITS - 1 - max_entries.leading_zeros())
+}
+
 impl TranspositionTable {
-    pub fn init() {
-        unsafe {
-            TRANSPOSITION_TABLE = Some(RwLock::new(TranspositionTable {
-                entries: vec![None; TRANSPOSITION_ENTRIES],
-            }));
+    /// Allocates a new empty table that uses at most `size_mb` megabytes
+    pub fn new(size_mb: usize) -> Self {
+        let entries = entry_count(size_mb);
+
+        Self {
+            entries: (0..entries).map(|_| AtomicEntry::default()).collect(),
+            mask: entries - 1,
+            age: AtomicU8::new(0),
+        }
+    }
+
+    pub fn resize(&mut self, size_mb: usize) {
+        // Free the old table first so we never hold both in memory
+        self.entries = Vec::new();
+        *self = Self::new(size_mb);
+    }
+
+    pub fn clear(&self) {
+        for entry in &self.entries {
+            entry.store(PackedEntry::default());
         }
+        self.age.store(0, Ordering::Relaxed);
     }
 
-    pub fn get(key: u64) -> Option<TranspositionEntry> {
-        let transposition_table = unsafe { TRANSPOSITION_TABLE.as_ref() }.unwrap().read().unwrap();
+    /// Marks all current entries as old so they are preferred for replacement
+    pub fn new_search(&self) {
+        let age = self.age.load(Ordering::Relaxed);
+        self.age.store((age + 1) & ((1 << AGE_BITS) - 1), Ordering::Relaxed);
+    }
 
-        if let Some(entry) = transposition_table.entries[key as usize % TRANSPOSITION_ENTRIES] {
-            return if entry.key == key { Some(entry) } else { None };
+    pub fn get(&self, key: u64) -> Option<TranspositionEntry> {
+        let entry = self.entries[key as usize & self.mask].load();
+        if entry.key == key {
+            entry.unpack()
+        } else {
+            None
         }
-        None
     }
 
-    pub fn set(key: u64, entry: TranspositionEntry) {
-        let mut transposition_table = unsafe { TRANSPOSITION_TABLE.as_ref() }.unwrap().write().unwrap();
+    /// Estimates how full the table is in permille by looking at the first entries
+    pub fn hashfull(&self) -> u32 {
+        let age = self.age.load(Ordering::Relaxed);
+
+        let sample = self.entries.iter().take(1000).map(|e| e.load());
+        let used = sample.filter(|e| !e.is_empty() && e.age() == age).count();
+        (used * 1000 / self.entries.len().min(1000)) as u32
+    }
+
+    pub fn set(&self, key: u64, entry: TranspositionEntry) {
+        let age = self.age.load(Ordering::Relaxed);
+        let slot = &self.entries[key as usize & self.mask];
+        let old = slot.load();
+
+        // Keep deeper entries of the current search for other positions
+        if !old.is_empty() && old.key != key && old.age() == age && old.depth() > entry.depth {
+            return;
+        }
+
+        slot.store(PackedEntry::pack(&entry, age));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn pack_entry_test() {
+        let m = Move {
+            from: 52,
+            to: 60,
+            promote_to: Some(PieceType::Queen),
+            ..Default::default()
+        };
+
+        for (score, depth) in [(0, 0), (-999_998, 12), (999_999, -5), (35, 127)] {
+            let entry = TranspositionEntry {
+                key: 0x1234_5678_9abc_def0,
+                entry_type: TranspositionEntryType::UpperBound,
+                depth,
+                score,
+                best_move: Some(m),
+            };
+
+            let unpacked = PackedEntry::pack(&entry, 63).unpack().unwrap();
+            assert_eq!(unpacked.key, entry.key);
+            assert!(unpacked.entry_type == TranspositionEntryType::UpperBound);
+            assert_eq!(unpacked.depth, depth);
+            assert_eq!(unpacked.score, score);
+            assert_eq!(unpacked.best_move, Some(m));
+            assert_eq!(PackedEntry::pack(&entry, 63).age(), 63);
+        }
+
+        assert!(PackedEntry::default().unpack().is_none());
+    }
+
+    #[test]
+    fn torn_entry_test() {
+        let transposition_table = TranspositionTable::new(1);
+        let entry = TranspositionEntry {
+            key: 42,
+            entry_type: TranspositionEntryType::Exact,
+            depth: 3,
+            score: 100,
+            best_move: None,
+        };
+
+        transposition_table.set(42, entry);
+        assert_eq!(transposition_table.get(42).unwrap().score, 100);
+
+        // Simulate another thread having overwritten only the data half of the entry
+        let slot = &transposition_table.entries[42];
+        let overwritten = PackedEntry::pack(&TranspositionEntry { score: -5, ..entry }, 0);
+        slot.data.store(overwritten.data, Ordering::Relaxed);
+        assert!(transposition_table.get(42).is_none());
+    }
 
-        transposition_table.entries[key as usize % TRANSPOSITION_ENTRIES] = Some(entry);
+    #[test]
+    fn entry_count_test() {
+        assert_eq!(entry_count(1), 1 << 16);
+        assert_eq!(entry_count(3), 1 << 17);
+        assert_eq!(entry_count(64), 1 << 22);
+        assert_eq!(entry_count(0), 1);
     }
 }
@@ -867,6 +867,17 @@ impl Zobrist {
         hash
     }
 
+    /// Updates the hash for passing the turn without moving a piece
+    pub fn null_move_update(mut hash: u64, state: &ChessState) -> u64 {
+        hash ^= z_turn(PieceColor::White);
+
+        if let Some(t) = state.en_passant_target {
+            hash ^= z_en_passant(t);
+        }
+
+        hash
+    }
+
     pub fn inc_update(mut hash: u64, state: &ChessState, m: &Move) -> u64 {
         hash ^= z_turn(PieceColor::White);
 